@@ -0,0 +1 @@
+ALTER TABLE mod_pk_guilds ADD COLUMN assign_fronter_roles BOOL NOT NULL DEFAULT false;
@@ -0,0 +1,5 @@
+-- TODO: Check if we error on overflowing 9 223 372 036 854 775 807
+CREATE TABLE mod_pk_role_sync (
+    guild_id BIGINT PRIMARY KEY,
+    admin_channel_id BIGINT
+);
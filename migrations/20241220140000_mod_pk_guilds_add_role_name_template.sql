@@ -0,0 +1,2 @@
+ALTER TABLE mod_pk_guilds ADD COLUMN role_name_template VARCHAR(100) NOT NULL DEFAULT '{name} (Alter)';
+ALTER TABLE mod_pk_guilds ADD COLUMN role_marker VARCHAR(100) NOT NULL DEFAULT ' (Alter)';
@@ -0,0 +1,8 @@
+-- TODO: Check if we error on overflowing 9 223 372 036 854 775 807
+CREATE TABLE mod_pk_roles (
+    guild_id BIGINT NOT NULL,
+    member_uuid UUID NOT NULL,
+    role_id BIGINT NOT NULL UNIQUE,
+
+    PRIMARY KEY(guild_id, member_uuid)
+);
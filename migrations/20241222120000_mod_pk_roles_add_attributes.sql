@@ -0,0 +1,5 @@
+ALTER TABLE mod_pk_guilds ADD COLUMN role_icons BOOL NOT NULL DEFAULT false;
+ALTER TABLE mod_pk_guilds ADD COLUMN role_hoist BOOL NOT NULL DEFAULT false;
+ALTER TABLE mod_pk_guilds ADD COLUMN role_order VARCHAR(16) NOT NULL DEFAULT 'none';
+ALTER TABLE mod_pk_guilds ADD COLUMN role_order_group VARCHAR(6);
+ALTER TABLE mod_pk_roles ADD COLUMN icon_url TEXT;
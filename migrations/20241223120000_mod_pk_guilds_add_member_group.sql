@@ -0,0 +1 @@
+ALTER TABLE mod_pk_guilds ADD COLUMN member_group VARCHAR(6);
@@ -0,0 +1,3 @@
+-- NOTE: encrypted tokens don't fit in CHAR(64), converting to TEXT also strips
+--       the padding CHAR added to existing plaintext tokens
+ALTER TABLE mod_pk_guilds ALTER COLUMN token TYPE TEXT;
@@ -0,0 +1 @@
+ALTER TABLE mod_pk_guilds ADD COLUMN verified BOOL NOT NULL DEFAULT false;
@@ -0,0 +1,22 @@
+-- NOTE: guilds can have multiple systems, settings, fronter categories and
+--       alter roles are tracked per system
+ALTER TABLE mod_pk_guilds DROP CONSTRAINT mod_pk_guilds_pkey;
+ALTER TABLE mod_pk_guilds DROP CONSTRAINT mod_pk_guilds_guild_id_user_id_key;
+ALTER TABLE mod_pk_guilds ADD PRIMARY KEY (guild_id, system_id);
+
+ALTER TABLE mod_pk_fronters ADD COLUMN system_id VARCHAR(6);
+UPDATE mod_pk_fronters SET system_id = mod_pk_guilds.system_id
+    FROM mod_pk_guilds WHERE mod_pk_fronters.guild_id = mod_pk_guilds.guild_id;
+DELETE FROM mod_pk_fronters WHERE system_id IS NULL;
+ALTER TABLE mod_pk_fronters ALTER COLUMN system_id SET NOT NULL;
+ALTER TABLE mod_pk_fronters DROP CONSTRAINT mod_pk_fronters_pkey;
+ALTER TABLE mod_pk_fronters DROP CONSTRAINT mod_pk_fronters_guild_id_category_id_key;
+ALTER TABLE mod_pk_fronters ADD PRIMARY KEY (guild_id, system_id);
+-- channels in categories shared between systems are prefixed per system
+ALTER TABLE mod_pk_fronters ADD COLUMN prefix VARCHAR(32) NOT NULL DEFAULT '';
+
+ALTER TABLE mod_pk_roles ADD COLUMN system_id VARCHAR(6);
+UPDATE mod_pk_roles SET system_id = mod_pk_guilds.system_id
+    FROM mod_pk_guilds WHERE mod_pk_roles.guild_id = mod_pk_guilds.guild_id;
+DELETE FROM mod_pk_roles WHERE system_id IS NULL;
+ALTER TABLE mod_pk_roles ALTER COLUMN system_id SET NOT NULL;
@@ -0,0 +1,3 @@
+-- NOTE: dispatch webhooks identify systems by uuid, not by their short id
+ALTER TABLE mod_pk_guilds ADD COLUMN system_uuid UUID;
+ALTER TABLE mod_pk_guilds ADD COLUMN webhook_token TEXT;
@@ -0,0 +1,12 @@
+ALTER TABLE mod_pk_fronters ADD COLUMN name_template VARCHAR(100) NOT NULL DEFAULT '{display_name}';
+
+-- NOTE: fronter channels are tracked by PluralKit member, so changing how they're
+--       named renames them instead of recreating them
+CREATE TABLE mod_pk_fronter_channels (
+    guild_id BIGINT NOT NULL,
+    system_id VARCHAR(6) NOT NULL,
+    member_uuid UUID NOT NULL,
+    channel_id BIGINT NOT NULL UNIQUE,
+
+    PRIMARY KEY(guild_id, system_id, member_uuid)
+);
@@ -0,0 +1,6 @@
+-- NOTE: fronters can be shown in other places than a category of voice channels,
+--       so category_id becomes the channel they're shown in
+ALTER TABLE mod_pk_fronters RENAME COLUMN category_id TO channel_id;
+ALTER TABLE mod_pk_fronters ADD COLUMN display VARCHAR(16) NOT NULL DEFAULT 'channels';
+-- message that's kept up to date when fronters are shown as a message
+ALTER TABLE mod_pk_fronters ADD COLUMN message_id BIGINT;
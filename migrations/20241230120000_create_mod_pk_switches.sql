@@ -0,0 +1,22 @@
+CREATE TABLE mod_pk_switch_logs (
+    guild_id BIGINT NOT NULL,
+    system_id VARCHAR(6) NOT NULL,
+    channel_id BIGINT NOT NULL,
+
+    PRIMARY KEY(guild_id, system_id)
+);
+
+-- NOTE: member names are stored as they were at the time of the switch, so the
+--       history still makes sense after members are renamed or deleted
+CREATE TABLE mod_pk_switches (
+    guild_id BIGINT NOT NULL,
+    system_id VARCHAR(6) NOT NULL,
+    switch_uuid UUID NOT NULL,
+    switched_at TIMESTAMP NOT NULL,
+    member_uuids UUID[] NOT NULL,
+    member_names TEXT[] NOT NULL,
+
+    PRIMARY KEY(guild_id, system_id, switch_uuid)
+);
+
+CREATE INDEX mod_pk_switches_switched_at ON mod_pk_switches (guild_id, system_id, switched_at);
@@ -0,0 +1,3 @@
+-- NOTE: fronters that were last shown, so discord is only updated when they change
+ALTER TABLE mod_pk_fronters ADD COLUMN last_fronter_uuids UUID[];
+ALTER TABLE mod_pk_fronters ADD COLUMN last_fronter_names TEXT[];
@@ -0,0 +1 @@
+ALTER TABLE mod_pk_fronters ADD COLUMN fronter_order VARCHAR(16) NOT NULL DEFAULT 'pk_order';
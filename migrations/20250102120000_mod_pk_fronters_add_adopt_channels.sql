@@ -0,0 +1,4 @@
+-- NOTE: fronter channels created before they were tracked get adopted by name once,
+--       new setups start out tracking every channel they create
+ALTER TABLE mod_pk_fronters ADD COLUMN adopt_channels BOOL NOT NULL DEFAULT true;
+ALTER TABLE mod_pk_fronters ALTER COLUMN adopt_channels SET DEFAULT false;
@@ -0,0 +1,3 @@
+-- NOTE: fronter updates that keep failing get disabled until they're set up again
+ALTER TABLE mod_pk_fronters ADD COLUMN failures INT NOT NULL DEFAULT 0;
+ALTER TABLE mod_pk_fronters ADD COLUMN disabled BOOL NOT NULL DEFAULT false;
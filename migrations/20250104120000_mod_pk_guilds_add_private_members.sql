@@ -0,0 +1 @@
+ALTER TABLE mod_pk_guilds ADD COLUMN private_members VARCHAR(16) NOT NULL DEFAULT 'hide';
@@ -14,9 +14,22 @@ pub(crate) struct DatabaseConfig {
     pub(crate) url: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub(crate) struct PkConfig {
+    // base64 encoded 32 byte key used to encrypt PluralKit tokens
+    pub(crate) token_key: String,
+    // previous key, only used when rotating keys
+    pub(crate) old_token_key: Option<String>,
+    // address to listen on for PluralKit dispatch webhooks, disabled if unset
+    pub(crate) webhook_listen: Option<String>,
+    // public url of the webhook endpoint, shown to users setting up webhooks
+    pub(crate) webhook_url: Option<String>,
+}
+
 pub(crate) struct Config {
     pub(crate) bot: BotConfig,
     pub(crate) db: DatabaseConfig,
+    pub(crate) pk: PkConfig,
 }
 
 pub(crate) fn load_config() -> Result<Config, Error> {
@@ -24,5 +37,7 @@ pub(crate) fn load_config() -> Result<Config, Error> {
     let db: DatabaseConfig =
         serde_envfile::prefixed("DATABASE_").from_file(&PathBuf::from(".env"))?;
 
-    Ok(Config { bot, db })
+    let pk: PkConfig = serde_envfile::prefixed("TULPJE_PK_").from_file(&PathBuf::from(".env"))?;
+
+    Ok(Config { bot, db, pk })
 }
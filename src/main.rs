@@ -7,6 +7,7 @@ use sqlx::{
 };
 use tracing::{debug, info, log::LevelFilter};
 
+use crate::modules::pk::tokens::TokenCipher;
 use crate::types::Data;
 
 mod config;
@@ -59,6 +60,30 @@ async fn main() {
         .await
         .expect("error running migrations");
 
+    let pk_tokens = TokenCipher::new(&config.pk.token_key).expect("error loading pk token key");
+
+    // command-line mode to re-encrypt stored PluralKit tokens with a new key
+    if std::env::args().nth(1).as_deref() == Some("rotate-pk-key") {
+        let old_pk_tokens = TokenCipher::new(
+            config
+                .pk
+                .old_token_key
+                .as_deref()
+                .expect("TULPJE_PK_OLD_TOKEN_KEY is required to rotate keys"),
+        )
+        .expect("error loading old pk token key");
+
+        let count = modules::pk::tokens::rotate_tokens(&db, &old_pk_tokens, &pk_tokens)
+            .await
+            .expect("error rotating pk token key");
+        info!("re-encrypted {} PluralKit token(s) with the new key", count);
+        return;
+    }
+
+    modules::pk::tokens::encrypt_plaintext_tokens(&db, &pk_tokens)
+        .await
+        .expect("error encrypting plaintext pk tokens");
+
     let intents = serenity::GatewayIntents::all();
     let options = poise::FrameworkOptions {
         pre_command: |ctx| {
@@ -84,9 +109,19 @@ async fn main() {
         ..Default::default()
     };
 
-    let data = Arc::new(Data::new(db));
+    // dispatch webhooks are only enabled when there's an address to listen on
+    let pk_webhook_listen = config.pk.webhook_listen;
+    let pk_webhook_url = pk_webhook_listen.as_ref().map(|_| {
+        config
+            .pk
+            .webhook_url
+            .expect("TULPJE_PK_WEBHOOK_URL is required when TULPJE_PK_WEBHOOK_LISTEN is set")
+    });
+
+    let data = Arc::new(Data::new(db, pk_tokens, pk_webhook_url));
     let handler = events::EventHandler { data: data.clone() };
     let event_handler_emoji = modules::emoji::event_handler::EventHandler { data: data.clone() };
+    let event_handler_pk = modules::pk::event_handler::EventHandler { data: data.clone() };
 
     let framework = poise::Framework::builder()
         .options(options)
@@ -128,6 +163,9 @@ async fn main() {
                 // register module tasks
                 modules::stats::start_tasks(ctx.to_owned(), data.clone());
                 modules::pk::start_tasks(ctx.to_owned(), data.clone());
+                if let Some(listen) = pk_webhook_listen {
+                    modules::pk::start_webhook(ctx.to_owned(), data.clone(), listen);
+                }
 
                 Ok(data.clone())
             })
@@ -145,6 +183,7 @@ async fn main() {
         .cache_settings(cache_settings)
         .event_handler(handler)
         .event_handler(event_handler_emoji)
+        .event_handler(event_handler_pk)
         .framework(framework)
         .await;
 
@@ -2,24 +2,81 @@ use std::sync::Arc;
 
 use poise::serenity_prelude::{self as serenity};
 
+use tracing::error;
+
 use crate::spawn_task;
 use crate::types::{Data, Error};
 
+pub(crate) mod api;
 pub(crate) mod commands;
 pub(crate) mod db;
+pub(crate) mod event_handler;
 pub(crate) mod fronters;
+pub(crate) mod messages;
+pub(crate) mod privacy;
 pub(crate) mod roles;
+pub(crate) mod shared;
+pub(crate) mod switches;
+pub(crate) mod template;
+pub(crate) mod tokens;
+pub(crate) mod webhook;
+
+// PluralKit member for tests, `fields` override the defaults
+#[cfg(test)]
+pub(crate) fn test_member(fields: serde_json::Value) -> pkrs::model::Member {
+    let mut member = serde_json::json!({
+        "id": "abcde",
+        "uuid": "00000000-0000-0000-0000-000000000000",
+        "name": "member",
+        "created": null,
+        "proxy_tags": [],
+        "keep_proxy": false,
+        "last_message_timestamp": null,
+    });
+    if let (Some(member), serde_json::Value::Object(fields)) = (member.as_object_mut(), fields) {
+        member.extend(fields);
+    }
+    serde_json::from_value(member).unwrap()
+}
 
 pub(crate) fn commands() -> Vec<poise::Command<Arc<Data>, Error>> {
     vec![
         commands::setup_pk(),
+        commands::setup_pk_group(),
+        commands::setup_pk_webhook(),
+        commands::pk_settings(),
         fronters::commands::setup_fronters(),
+        fronters::commands::setup_fronter_display(),
+        fronters::commands::setup_fronter_names(),
+        fronters::commands::setup_fronter_order(),
         fronters::commands::update_fronters(),
-        roles::update_member_roles(),
+        fronters::commands::teardown_fronters(),
+        roles::commands::update_member_roles(),
+        roles::commands::assign_fronter_roles(),
+        roles::commands::setup_role_sync(),
+        roles::commands::setup_role_names(),
+        roles::commands::setup_role_attributes(),
+        switches::commands::setup_switch_log(),
+        switches::commands::fronter_history(),
+        switches::commands::front_stats(),
+        messages::commands::who_sent(),
     ]
 }
 
-// TODO: Replace tokio_schedule with something using tokio::time::interval and MissedTickBehavior tbh
 pub(crate) fn start_tasks(ctx: serenity::Context, data: Arc<Data>) {
     spawn_task!(60, fronters::tasks::update_fronters, ctx, data);
+    spawn_task!(600, fronters::tasks::update_dispatch_fronters, ctx, data);
+    spawn_task!(60, roles::tasks::update_fronter_roles, ctx, data);
+    spawn_task!(600, roles::tasks::update_dispatch_fronter_roles, ctx, data);
+    spawn_task!(300, roles::tasks::sync_member_roles, ctx, data);
+    spawn_task!(60, switches::tasks::log_switches, ctx, data);
+    spawn_task!(600, switches::tasks::log_dispatch_switches, ctx, data);
+}
+
+pub(crate) fn start_webhook(ctx: serenity::Context, data: Arc<Data>, listen: String) {
+    tokio::spawn(async move {
+        if let Err(err) = webhook::serve(ctx, data, listen).await {
+            error!("error running PluralKit webhook server: {}", err);
+        }
+    });
 }
@@ -0,0 +1,452 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use pkrs::model::{Member, Message, Switch};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_either::StringOrStruct;
+use sqlx::types::{
+    chrono::{DateTime, Utc},
+    Uuid,
+};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+const PK_API_URL: &str = "https://api.pluralkit.me/v2";
+const USER_AGENT: &str = "tulpje (https://github.com/z0w13/tulpje)";
+
+// PluralKit allows 10 requests per second, stay slightly below that
+const REQUEST_INTERVAL: Duration = Duration::from_millis(110);
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+// fronters change most often, and are polled every minute anyway
+const SYSTEM_TTL: Duration = Duration::from_secs(300);
+const MEMBERS_TTL: Duration = Duration::from_secs(120);
+const FRONTERS_TTL: Duration = Duration::from_secs(30);
+
+// who sent a proxied message never changes, but there's a lot of them, so only
+// recent lookups are kept
+const MESSAGE_TTL: Duration = Duration::from_secs(3600);
+const MAX_CACHED_MESSAGES: usize = 1000;
+
+// enough to tell how long most fronters have been fronting for
+const LATEST_SWITCHES: u32 = 100;
+
+// NOTE: pkrs' System doesn't include the uuid, which dispatch webhooks identify
+//       systems by, so we only deserialize what we need ourselves
+#[derive(Deserialize, Debug)]
+pub(crate) struct PkSystem {
+    pub(crate) id: String,
+    pub(crate) uuid: Uuid,
+    pub(crate) name: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Fronters {
+    // None if the system never registered a switch
+    pub(crate) switch_id: Option<Uuid>,
+    // when the current switch happened, None if nobody is fronting
+    pub(crate) since: Option<DateTime<Utc>>,
+    pub(crate) members: Vec<Member>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    InvalidToken,
+    // private, meaning of this depends on the endpoint
+    Forbidden,
+    NotFound,
+    Unavailable(String),
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidToken => fmt.write_str("PluralKit token is invalid"),
+            Self::Forbidden => fmt.write_str("not allowed to access this, it might be private"),
+            Self::NotFound => fmt.write_str("not found"),
+            Self::Unavailable(err) => write!(fmt, "PluralKit API unavailable: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[derive(Hash, Eq, PartialEq, Clone)]
+struct CacheKey {
+    // system the response belongs to, so dispatch webhooks can invalidate it
+    system: String,
+    path: String,
+    // responses depend on what the token is allowed to see
+    token: String,
+}
+
+struct CacheEntry {
+    // None if PluralKit returned no content
+    body: Option<Arc<[u8]>>,
+    expires: Instant,
+}
+
+// PluralKit API client shared by all modules, spaces out requests to stay within
+// the rate limit, retries transient errors and caches responses for a short time
+pub(crate) struct PkApi {
+    client: reqwest::Client,
+    // earliest time the next request is allowed to go out
+    next_request: Mutex<Instant>,
+    cache: DashMap<CacheKey, CacheEntry>,
+    // one lock per request, so concurrent requests for the same data fetch it once
+    pending: DashMap<CacheKey, Arc<Mutex<()>>>,
+    // proxied messages by message id, not tied to a system like the rest
+    messages: DashMap<u64, CacheEntry>,
+}
+
+// NOTE: manual impl so tokens in the cache keys don't end up in logs
+impl std::fmt::Debug for PkApi {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("PkApi")
+            .field("cached", &self.cache.len())
+            .field("cached_messages", &self.messages.len())
+            .finish()
+    }
+}
+
+impl PkApi {
+    pub(crate) fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            next_request: Mutex::new(Instant::now()),
+            cache: DashMap::new(),
+            pending: DashMap::new(),
+            messages: DashMap::new(),
+        }
+    }
+
+    async fn wait_for_slot(&self) {
+        let slot = {
+            let mut next_request = self.next_request.lock().await;
+            let slot = (*next_request).max(Instant::now());
+            *next_request = slot + REQUEST_INTERVAL;
+            slot
+        };
+
+        tokio::time::sleep_until(slot.into()).await;
+    }
+
+    // hold off all requests, used when we get rate limited anyway
+    async fn pause(&self, duration: Duration) {
+        let mut next_request = self.next_request.lock().await;
+        *next_request = (*next_request).max(Instant::now() + duration);
+    }
+
+    // uncached request, returns None if PluralKit returned no content
+    pub(crate) async fn request(
+        &self,
+        path: &str,
+        token: &str,
+    ) -> Result<Option<Arc<[u8]>>, ApiError> {
+        let mut attempt = 1;
+
+        loop {
+            self.wait_for_slot().await;
+
+            let mut request = self
+                .client
+                .get(format!("{}/{}", PK_API_URL, path))
+                .header("User-Agent", USER_AGENT);
+            if !token.is_empty() {
+                request = request.header("Authorization", token);
+            }
+
+            let err = match request.send().await {
+                Ok(response) => match response.status() {
+                    reqwest::StatusCode::NO_CONTENT => return Ok(None),
+                    status if status.is_success() => {
+                        return response
+                            .bytes()
+                            .await
+                            .map(|body| Some(Arc::from(body.as_ref())))
+                            .map_err(|err| ApiError::Unavailable(err.to_string()));
+                    }
+                    reqwest::StatusCode::UNAUTHORIZED => return Err(ApiError::InvalidToken),
+                    reqwest::StatusCode::FORBIDDEN => return Err(ApiError::Forbidden),
+                    reqwest::StatusCode::NOT_FOUND => return Err(ApiError::NotFound),
+                    status @ reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                        self.pause(retry_delay(attempt)).await;
+                        status.to_string()
+                    }
+                    status if status.is_server_error() => status.to_string(),
+                    status => return Err(ApiError::Unavailable(status.to_string())),
+                },
+                Err(err) => err.to_string(),
+            };
+
+            if attempt >= MAX_ATTEMPTS {
+                return Err(ApiError::Unavailable(err));
+            }
+
+            warn!(path, attempt, "PluralKit request failed, retrying: {}", err);
+            tokio::time::sleep(retry_delay(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<Option<Arc<[u8]>>> {
+        let entry = self.cache.get(key)?;
+        if entry.expires > Instant::now() {
+            return Some(entry.body.clone());
+        }
+
+        drop(entry);
+        self.cache.remove(key);
+        None
+    }
+
+    async fn cached(
+        &self,
+        system_id: &str,
+        path: &str,
+        token: &str,
+        ttl: Duration,
+    ) -> Result<Option<Arc<[u8]>>, ApiError> {
+        let key = CacheKey {
+            system: system_id.to_owned(),
+            path: path.to_owned(),
+            token: token.to_owned(),
+        };
+
+        if let Some(body) = self.lookup(&key) {
+            return Ok(body);
+        }
+
+        let lock = self.pending.entry(key.clone()).or_default().clone();
+        let _guard = lock.lock().await;
+
+        // another request might've fetched it while we were waiting
+        if let Some(body) = self.lookup(&key) {
+            return Ok(body);
+        }
+
+        debug!(system_id, path, "fetching from PluralKit");
+        let result = self.request(path, token).await;
+        if let Ok(body) = &result {
+            self.cache.insert(
+                key.clone(),
+                CacheEntry {
+                    body: body.clone(),
+                    expires: Instant::now() + ttl,
+                },
+            );
+        }
+        self.pending.remove(&key);
+
+        result
+    }
+
+    // forget everything we know about a system, e.g. after a dispatch webhook
+    pub(crate) fn invalidate_system(&self, system_id: &str) {
+        self.cache.retain(|key, _| key.system != system_id);
+    }
+
+    pub(crate) async fn get_system(
+        &self,
+        system_id: &str,
+        token: &str,
+    ) -> Result<PkSystem, ApiError> {
+        let body = self
+            .cached(
+                system_id,
+                &format!("systems/{}", system_id),
+                token,
+                SYSTEM_TTL,
+            )
+            .await?;
+        parse(body.as_deref())
+    }
+
+    pub(crate) async fn get_system_members(
+        &self,
+        system_id: &str,
+        token: &str,
+    ) -> Result<Vec<Member>, ApiError> {
+        let body = self
+            .cached(
+                system_id,
+                &format!("systems/{}/members", system_id),
+                token,
+                MEMBERS_TTL,
+            )
+            .await?;
+        parse(body.as_deref())
+    }
+
+    pub(crate) async fn get_group_members(
+        &self,
+        system_id: &str,
+        group_id: &str,
+        token: &str,
+    ) -> Result<Vec<Member>, ApiError> {
+        let body = self
+            .cached(
+                system_id,
+                &format!("groups/{}/members", group_id),
+                token,
+                MEMBERS_TTL,
+            )
+            .await?;
+        parse(body.as_deref())
+    }
+
+    pub(crate) async fn get_system_fronters(
+        &self,
+        system_id: &str,
+        token: &str,
+    ) -> Result<Fronters, ApiError> {
+        let body = self
+            .cached(
+                system_id,
+                &format!("systems/{}/fronters", system_id),
+                token,
+                FRONTERS_TTL,
+            )
+            .await?;
+        parse_fronters(body.as_deref())
+    }
+
+    // latest switches, newest first, cached like the current fronters
+    pub(crate) async fn get_latest_switches(
+        &self,
+        system_id: &str,
+        token: &str,
+    ) -> Result<Vec<Switch>, ApiError> {
+        let body = self
+            .cached(
+                system_id,
+                &format!("systems/{}/switches?limit={}", system_id, LATEST_SWITCHES),
+                token,
+                FRONTERS_TTL,
+            )
+            .await?;
+        parse(body.as_deref())
+    }
+
+    // switches before `before`, or the latest ones, newest first
+    // NOTE: not cached, these are only requested for stats and don't change
+    pub(crate) async fn get_system_switches(
+        &self,
+        system_id: &str,
+        before: Option<DateTime<Utc>>,
+        limit: u32,
+        token: &str,
+    ) -> Result<Vec<Switch>, ApiError> {
+        let mut path = format!("systems/{}/switches?limit={}", system_id, limit);
+        if let Some(before) = before {
+            path += &format!("&before={}", before.format("%Y-%m-%dT%H:%M:%SZ"));
+        }
+
+        let body = self.request(&path, token).await?;
+        parse(body.as_deref())
+    }
+
+    // a proxied message, looked up by either the proxied or the original message id
+    // NOTE: doesn't need a token, PluralKit only includes what's public
+    pub(crate) async fn get_message(&self, message_id: u64) -> Result<Message, ApiError> {
+        let cached = self
+            .messages
+            .get(&message_id)
+            .filter(|entry| entry.expires > Instant::now())
+            .map(|entry| entry.body.clone());
+        if let Some(body) = cached {
+            return parse(body.as_deref());
+        }
+
+        debug!(message_id, "fetching message from PluralKit");
+        let body = self
+            .request(&format!("messages/{}", message_id), "")
+            .await?;
+
+        if self.messages.len() >= MAX_CACHED_MESSAGES {
+            let now = Instant::now();
+            self.messages.retain(|_, entry| entry.expires > now);
+        }
+        // still full of recent lookups, they'll expire soon enough
+        if self.messages.len() < MAX_CACHED_MESSAGES {
+            self.messages.insert(
+                message_id,
+                CacheEntry {
+                    body: body.clone(),
+                    expires: Instant::now() + MESSAGE_TTL,
+                },
+            );
+        }
+
+        parse(body.as_deref())
+    }
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BACKOFF * 2u32.pow(attempt.saturating_sub(1))
+}
+
+fn parse<T: DeserializeOwned>(body: Option<&[u8]>) -> Result<T, ApiError> {
+    serde_json::from_slice(body.unwrap_or_default())
+        .map_err(|err| ApiError::Unavailable(format!("invalid response: {}", err)))
+}
+
+// NOTE: PluralKit returns no content when nobody is fronting
+fn parse_fronters(body: Option<&[u8]>) -> Result<Fronters, ApiError> {
+    let Some(body) = body else {
+        return Ok(Fronters::default());
+    };
+
+    let switch = parse::<Switch>(Some(body))?;
+    Ok(Fronters {
+        switch_id: Some(switch.id),
+        since: DateTime::from_timestamp(switch.timestamp.unix_timestamp(), 0),
+        members: switch
+            .members
+            .into_iter()
+            .filter_map(|m| match m {
+                StringOrStruct::String(_) => None,
+                StringOrStruct::Struct(member) => Some(member),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    #[test]
+    fn retry_delay_test() {
+        assert_eq!(retry_delay(1), Duration::from_millis(500));
+        assert_eq!(retry_delay(2), Duration::from_millis(1000));
+        assert_eq!(retry_delay(3), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn parse_fronters_test() {
+        let fronters = parse_fronters(None).unwrap();
+        assert!(fronters.switch_id.is_none());
+        assert!(fronters.since.is_none());
+        assert!(fronters.members.is_empty());
+
+        let body = serde_json::to_vec(&serde_json::json!({
+            "id": "cc6c51ad-6a5a-4e5c-8c1c-bb7e3b8e4f01",
+            "timestamp": "2024-12-28T12:00:00Z",
+            "members": [
+                "abcde",
+                test_member(serde_json::json!({"id": "fghij", "name": "Alex"})),
+            ],
+        }))
+        .unwrap();
+        let fronters = parse_fronters(Some(&body)).unwrap();
+        assert_eq!(fronters.since.unwrap().timestamp(), 1735387200);
+        assert_eq!(fronters.members.len(), 1);
+        assert_eq!(fronters.members[0].name, "Alex");
+
+        assert!(parse_fronters(Some(b"not json")).is_err());
+    }
+}
@@ -1,22 +1,60 @@
-use pkrs::model::PkId;
-use tracing::debug;
+use std::time::Duration;
+
+use poise::serenity_prelude::{self as serenity};
+use tracing::{debug, error};
 
 use super::db;
-use crate::types::{Context, Error};
+use super::fronters::db as fronters_db;
+use super::fronters::display::{FronterDisplay, FronterOrder};
+use super::fronters::teardown::teardown_fronters;
+use super::privacy::PrivateMembers;
+use super::roles::db as roles_db;
+use super::shared::{
+    confirm_changes, get_command_system, get_group_member_ids, get_system_members, validate_system,
+    verify_ownership, SystemError,
+};
+use super::switches::db as switches_db;
+use crate::types::{ApplicationContext, Context, Error};
+
+// how long to wait for the token to be entered
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(300);
 
-// TODO: command to see current settings
+// asked for separately, slash command options are visible to anyone who clicks
+// on the command
+#[derive(Debug, poise::Modal)]
+#[name = "PluralKit Token"]
+struct TokenModal {
+    #[name = "Token"]
+    #[placeholder = "get one with pk;token in DMs with PluralKit"]
+    token: String,
+}
 
 #[poise::command(
     slash_command,
     guild_only = true,
+    ephemeral,
     rename = "setup-pk",
     default_member_permissions = "MANAGE_GUILD"
 )]
 pub(crate) async fn setup_pk(
-    ctx: Context<'_>,
+    ctx: ApplicationContext<'_>,
     #[description = "system id"] system_id: String,
-    #[description = "(optional) PluralKit token"] token: Option<String>,
+    #[description = "(optional) ask for a PluralKit token, needed for private information"]
+    with_token: Option<bool>,
+    #[description = "(optional) how to show members that are private on PluralKit, hidden by default"]
+    private_members: Option<PrivateMembers>,
 ) -> Result<(), Error> {
+    // a modal has to be the first response
+    let token = match with_token.unwrap_or(false) {
+        true => {
+            match poise::execute_modal::<_, _, TokenModal>(ctx, None, Some(TOKEN_TIMEOUT)).await? {
+                Some(modal) => Some(modal.token),
+                None => return Ok(()),
+            }
+        }
+        false => None,
+    };
+
     let guild = ctx.guild().ok_or("couldn't fetch guild")?.to_owned();
     let user_id = ctx.author().id;
 
@@ -35,43 +73,499 @@ pub(crate) async fn setup_pk(
         return Ok(());
     }
 
+    let token = token.map(|t| t.trim().to_owned()).filter(|t| !t.is_empty());
+
+    ctx.defer_ephemeral().await?;
+
+    // validate before saving, so we don't keep erroring on a system we can't use
+    let pk = &ctx.data().pk;
+    let system = match validate_system(pk, &system_id, token.as_deref().unwrap_or("")).await {
+        Ok(system) => system,
+        Err(err) => {
+            ctx.reply(format!("error: {}", err)).await?;
+            return Ok(());
+        }
+    };
+
+    // only alter roles and front stats need the member list, so it's no reason to
+    // refuse the system
+    let members_private = matches!(
+        get_system_members(pk, &system_id, token.as_deref().unwrap_or("")).await,
+        Err(SystemError::MembersPrivate)
+    );
+
+    let verified = match verify_ownership(
+        pk,
+        &system_id,
+        token.as_deref().unwrap_or(""),
+        user_id.get(),
+    )
+    .await
+    {
+        Ok(verified) => verified,
+        Err(err) => {
+            ctx.reply(format!("error: {}", err)).await?;
+            return Ok(());
+        }
+    };
+
+    // a verified setup is kept when ownership can't be verified this time
+    let previously_verified = db::get_guild_systems(&ctx.data().db, guild.id.get())
+        .await?
+        .into_iter()
+        .any(|gs| gs.system_id == system_id && gs.verified);
+
     db::save_guild_settings(
         &ctx.data().db,
         guild.id.get(),
         user_id.get(),
         &system_id,
-        token.clone(),
+        system.uuid,
+        token
+            .as_deref()
+            .map(|t| ctx.data().pk_tokens.encrypt(t))
+            .transpose()?,
+        verified,
     )
     .await?;
 
-    let pk = pkrs::client::PkClient {
-        token: token.unwrap_or("".to_owned()),
-        ..Default::default()
-    };
-
-    // TODO: fix pkrs to actually handle 404s correctly
-    let system = match pk.get_system(&PkId(system_id.clone())).await {
-        Ok(system) => system,
-        Err(err) => {
-            ctx.reply(format!(
-                "PluralKit API is having issues or system doesn't exist: {:?}",
-                err
-            ))
+    // keep the current setting when re-running setup without specifying it
+    let private_members = match private_members {
+        Some(private_members) => {
+            db::save_private_members(
+                &ctx.data().db,
+                guild.id.get(),
+                &system_id,
+                private_members.id(),
+            )
             .await?;
-            return Ok(());
+            private_members
+        }
+        None => {
+            let gs = db::get_guild_systems(&ctx.data().db, guild.id.get())
+                .await?
+                .into_iter()
+                .find(|gs| gs.system_id == system_id)
+                .ok_or("couldn't find saved system")?;
+            PrivateMembers::try_from_string(&gs.private_members)?
         }
     };
 
     // Inform user of success
     let response_text = format!(
-        "PluralKit module setup with system: {}",
+        "PluralKit module setup with system: {}\n{}\nPrivate members: {}, {}.{}",
         match system.name {
             Some(system_name) => format!("{} (`{}`)", system_name, system_id),
             None => format!("`{}`", system_id),
-        }
+        },
+        match (verified, previously_verified) {
+            (true, _) => "System ownership verified.",
+            (false, true) => "System ownership couldn't be verified, the existing verified setup and its token were kept.",
+            (false, false) => "**System not verified**, fronters can't be shown in public channels. Provide a token of the system, or link your Discord account to it with `pk;link`, then run /setup-pk again.",
+        },
+        private_members.name(),
+        private_members.description(),
+        match members_private {
+            true => format!("\nNOTE: {}.", SystemError::MembersPrivate),
+            false => "".into(),
+        },
     );
 
     ctx.reply(response_text).await?;
 
     Ok(())
 }
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-pk-group",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_pk_group(
+    ctx: Context<'_>,
+    #[description = "(optional) only show members of this group, leave empty to show all members"]
+    group_id: Option<String>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let gs = get_command_system(ctx, system).await?;
+
+    let Some(group_id) = group_id else {
+        db::save_member_group(&ctx.data().db, guild_id.get(), &gs.system_id, None).await?;
+        ctx.reply("alter roles and fronters will include all members")
+            .await?;
+        return Ok(());
+    };
+
+    // sanitise and validate group id
+    let group_id = group_id.trim().replace("-", "").to_lowercase();
+    if !group_id.chars().all(|c| char::is_ascii_alphabetic(&c)) {
+        ctx.reply(format!("error: invalid group id, {}", group_id))
+            .await?;
+        return Ok(());
+    }
+
+    // make sure we can actually see the group's members before saving it
+    let token = ctx.data().pk_tokens.decrypt_stored(&gs.token)?;
+    let members = match get_group_member_ids(&ctx.data().pk, &gs.system_id, &group_id, &token).await
+    {
+        Ok(members) => members,
+        Err(err) => {
+            ctx.reply(format!("error: {}", err)).await?;
+            return Ok(());
+        }
+    };
+
+    db::save_member_group(
+        &ctx.data().db,
+        guild_id.get(),
+        &gs.system_id,
+        Some(group_id.clone()),
+    )
+    .await?;
+
+    ctx.reply(format!(
+        "alter roles and fronters will only include the {} member(s) of group `{}`",
+        members.len(),
+        group_id
+    ))
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-pk-webhook",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_pk_webhook(
+    ctx: Context<'_>,
+    #[description = "(optional) signing token sent by PluralKit, leave empty to poll for changes instead"]
+    signing_token: Option<String>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(webhook_url) = ctx.data().pk_webhook_url.clone() else {
+        ctx.reply("error: PluralKit webhooks aren't enabled for this bot")
+            .await?;
+        return Ok(());
+    };
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let gs = get_command_system(ctx, system).await?;
+
+    // dispatch webhooks identify systems by uuid, which older settings don't have
+    let token = ctx.data().pk_tokens.decrypt_stored(&gs.token)?;
+    let pk_system = ctx.data().pk.get_system(&gs.system_id, &token).await?;
+
+    let signing_token = signing_token
+        .map(|t| t.trim().to_owned())
+        .filter(|t| !t.is_empty());
+
+    db::save_webhook_token(
+        &ctx.data().db,
+        guild_id.get(),
+        &gs.system_id,
+        pk_system.uuid,
+        signing_token.clone(),
+    )
+    .await?;
+
+    ctx.reply(match signing_token {
+        Some(_) => "fronters will be updated as soon as PluralKit sends a change".to_string(),
+        None => format!(
+            "fronters will be polled for changes, for instant updates run `pk;s webhook {}` and run this command again with the signing token PluralKit sends you",
+            webhook_url
+        ),
+    })
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "pk-settings",
+    subcommands("settings_show", "settings_clear_token", "settings_remove"),
+    subcommand_required,
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn pk_settings(_ctx: Context<'_>) -> Result<(), Error> {
+    unreachable!("subcommand is required");
+}
+
+// only show the last few characters of the token so it can be recognised
+fn mask_token(token: &str) -> String {
+    let token = token.trim();
+    let visible: String = token
+        .chars()
+        .skip(token.chars().count().saturating_sub(4))
+        .collect();
+
+    format!("set (`****{}`)", visible)
+}
+
+#[poise::command(slash_command, guild_only = true, rename = "show")]
+pub(crate) async fn settings_show(
+    ctx: Context<'_>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let db = &ctx.data().db;
+
+    let gs = get_command_system(ctx, system).await?;
+    let fronter_category =
+        fronters_db::get_fronter_category(db, guild_id.get(), &gs.system_id).await?;
+    let role_sync = roles_db::get_role_sync_for_id(db, guild_id.get()).await?;
+    let switch_log = switches_db::get_switch_log(db, guild_id.get(), &gs.system_id).await?;
+    let token = ctx.data().pk_tokens.decrypt_stored(&gs.token)?;
+
+    let embed = serenity::CreateEmbed::new()
+        .title("PluralKit Settings")
+        .field("System", format!("`{}`", gs.system_id), true)
+        .field("Configured By", format!("<@{}>", gs.user_id), true)
+        .field("Verified", format!("{}", gs.verified), true)
+        .field(
+            "Updates",
+            match gs.webhook_token.is_some() {
+                true => "dispatch webhook",
+                false => "polling",
+            },
+            true,
+        )
+        .field(
+            "Token",
+            match token.is_empty() {
+                true => "not set".into(),
+                false => mask_token(&token),
+            },
+            true,
+        )
+        .field(
+            "Fronters",
+            match &fronter_category {
+                Some(fronters) if fronters.disabled => format!(
+                    "disabled after {} failed updates, run /update-fronters to retry",
+                    fronters.failures
+                ),
+                Some(fronters) if !fronters.prefix.is_empty() => {
+                    format!("<#{}> (prefix: `{}`)", fronters.channel_id, fronters.prefix)
+                }
+                Some(fronters) => format!(
+                    "{} in <#{}>",
+                    FronterDisplay::try_from_string(&fronters.display)?.name(),
+                    fronters.channel_id
+                ),
+                None => "not set".into(),
+            },
+            true,
+        )
+        .field(
+            "Fronter Names",
+            fronter_category
+                .as_ref()
+                .map_or("not set".into(), |f| format!("`{}`", f.name_template)),
+            true,
+        )
+        .field(
+            "Fronter Order",
+            match &fronter_category {
+                Some(f) => FronterOrder::try_from_string(&f.fronter_order)?.name(),
+                None => "not set",
+            },
+            true,
+        )
+        .field(
+            "Switch Log",
+            switch_log.map_or("disabled".into(), |log| format!("<#{}>", log.channel_id)),
+            true,
+        )
+        .field(
+            "Member Group",
+            gs.member_group
+                .map_or("all members".into(), |g| format!("`{}`", g)),
+            true,
+        )
+        .field(
+            "Private Members",
+            PrivateMembers::try_from_string(&gs.private_members)?.name(),
+            true,
+        )
+        .field(
+            "Role Names",
+            format!("`{}` (marker: `{}`)", gs.role_name_template, gs.role_marker),
+            true,
+        )
+        .field(
+            "Role Attributes",
+            format!(
+                "icons: {}, hoist: {}, order: {}",
+                gs.role_icons, gs.role_hoist, gs.role_order
+            ),
+            true,
+        )
+        .field(
+            "Role Sync",
+            match role_sync {
+                Some(sync) => match sync.admin_channel_id {
+                    Some(id) => format!("enabled, errors in <#{}>", id),
+                    None => "enabled".into(),
+                },
+                None => "disabled".into(),
+            },
+            true,
+        )
+        .field(
+            "Assign Fronter Roles",
+            format!("{}", gs.assign_fronter_roles),
+            true,
+        );
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only = true, rename = "clear-token")]
+pub(crate) async fn settings_clear_token(
+    ctx: Context<'_>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let gs = get_command_system(ctx, system).await?;
+
+    db::clear_token(&ctx.data().db, guild_id.get(), &gs.system_id).await?;
+
+    ctx.reply("PluralKit token removed, private information will no longer be shown")
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only = true, rename = "remove")]
+pub(crate) async fn settings_remove(
+    ctx: Context<'_>,
+    #[description = "also delete the fronter category, its channels and the alter roles"]
+    cleanup: Option<bool>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let db = &ctx.data().db;
+
+    if db::get_guild_systems(db, guild_id.get()).await?.is_empty() {
+        ctx.reply("error: PluralKit module not set-up, there's nothing to remove")
+            .await?;
+        return Ok(());
+    }
+    let gs = get_command_system(ctx, system).await?;
+
+    // deleting channels and roles can't be undone, so make sure it's intended
+    let cleanup = cleanup.unwrap_or(false);
+    let mci = match cleanup {
+        true => {
+            let Some(mci) = confirm_changes(
+                ctx,
+                poise::CreateReply::default().content(format!(
+                    "this removes the configuration for system `{}` and **deletes its fronter category, channels and {} alter role(s)**",
+                    gs.system_id,
+                    roles_db::get_managed_roles(db, guild_id.get(), &gs.system_id)
+                        .await?
+                        .len()
+                )),
+                "removal",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+            mci.create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content("removing configuration...")
+                        .components(vec![]),
+                ),
+            )
+            .await?;
+            Some(mci)
+        }
+        false => None,
+    };
+
+    let _lock = ctx.data().lock_system(guild_id.get(), &gs.system_id).await;
+    let mut failed = teardown_fronters(
+        ctx.serenity_context(),
+        ctx.data(),
+        guild_id,
+        &gs.system_id,
+        cleanup,
+    )
+    .await?;
+
+    if cleanup {
+        for role in roles_db::get_managed_roles(db, guild_id.get(), &gs.system_id).await? {
+            if let Err(err) = guild_id.delete_role(ctx, role.role_id).await {
+                error!(
+                    guild_id = guild_id.get(),
+                    role_id = role.role_id,
+                    "error deleting role: {}",
+                    err
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    roles_db::delete_managed_roles(db, guild_id.get(), &gs.system_id).await?;
+    switches_db::delete_switches(db, guild_id.get(), &gs.system_id).await?;
+    switches_db::delete_switch_log(db, guild_id.get(), &gs.system_id).await?;
+    db::delete_guild_settings(db, guild_id.get(), &gs.system_id).await?;
+
+    // role sync is shared between all systems in the guild
+    if db::get_guild_systems(db, guild_id.get()).await?.is_empty() {
+        roles_db::delete_role_sync(db, guild_id.get()).await?;
+    }
+
+    let message = match failed {
+        0 => format!(
+            "PluralKit configuration for system `{}` removed",
+            gs.system_id
+        ),
+        failed => format!(
+            "PluralKit configuration for system `{}` removed, {} channel(s)/role(s) couldn't be deleted",
+            gs.system_id, failed
+        ),
+    };
+    match mci {
+        Some(mci) => {
+            mci.edit_response(
+                ctx,
+                serenity::EditInteractionResponse::new().content(message),
+            )
+            .await?;
+        }
+        None => {
+            ctx.reply(message).await?;
+        }
+    }
+    Ok(())
+}
@@ -1,26 +1,47 @@
+use sqlx::types::Uuid;
+
 use crate::types::Error;
 
 #[derive(Debug)]
 pub(crate) struct ModPkGuildRow {
     pub(crate) guild_id: i64,
-    #[allow(dead_code)]
     pub(crate) user_id: i64,
     pub(crate) system_id: String,
     pub(crate) token: Option<String>,
+    pub(crate) assign_fronter_roles: bool,
+    pub(crate) role_name_template: String,
+    pub(crate) role_marker: String,
+    pub(crate) role_icons: bool,
+    pub(crate) role_hoist: bool,
+    pub(crate) role_order: String,
+    pub(crate) role_order_group: Option<String>,
+    pub(crate) member_group: Option<String>,
+    pub(crate) verified: bool,
+    // NOTE: signing tokens only allow triggering an update, so unlike PluralKit
+    //       tokens they're stored as-is
+    pub(crate) webhook_token: Option<String>,
+    pub(crate) private_members: String,
 }
+// NOTE: a verified system only changes hands, and its token only gets replaced,
+//       when the new user verified ownership as well, otherwise anyone re-running
+//       setup would take it over
 pub(crate) async fn save_guild_settings(
     db: &sqlx::PgPool,
     guild_id: u64,
     user_id: u64,
     system_id: &String,
+    system_uuid: Uuid,
     token: Option<String>,
+    verified: bool,
 ) -> Result<(), Error> {
     sqlx::query!(
-        "INSERT INTO mod_pk_guilds (guild_id, user_id, system_id, token) VALUES ($1, $2, $3, $4) ON CONFLICT (guild_id) DO UPDATE SET system_id = $3, token = $4",
+        "INSERT INTO mod_pk_guilds (guild_id, user_id, system_id, system_uuid, token, verified) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (guild_id, system_id) DO UPDATE SET user_id = CASE WHEN $6 THEN $2 ELSE mod_pk_guilds.user_id END, system_uuid = $4, token = CASE WHEN $6 OR NOT mod_pk_guilds.verified THEN $5 ELSE mod_pk_guilds.token END, verified = $6 OR mod_pk_guilds.verified",
         i64::try_from(guild_id)?,
         i64::try_from(user_id)?,
         system_id,
+        system_uuid,
         token,
+        verified,
     )
     .execute(db)
     .await?;
@@ -28,24 +49,259 @@ pub(crate) async fn save_guild_settings(
     Ok(())
 }
 
-pub(crate) async fn get_guild_settings_for_id(
+pub(crate) async fn get_guild_systems(
     db: &sqlx::PgPool,
     guild_id: u64,
-) -> Result<Option<ModPkGuildRow>, Error> {
+) -> Result<Vec<ModPkGuildRow>, Error> {
     Ok(sqlx::query_as!(
         ModPkGuildRow,
-        "SELECT guild_id, user_id, system_id, token FROM mod_pk_guilds WHERE guild_id = $1",
-        i64::try_from(guild_id)?
+        "SELECT guild_id, user_id, system_id, token, assign_fronter_roles, role_name_template, role_marker, role_icons, role_hoist, role_order, role_order_group, member_group, verified, webhook_token, private_members FROM mod_pk_guilds WHERE guild_id = $1 ORDER BY system_id",
+        i64::try_from(guild_id)?,
     )
-    .fetch_optional(db)
+    .fetch_all(db)
     .await?)
 }
 
 pub(crate) async fn get_guild_settings(db: &sqlx::PgPool) -> Result<Vec<ModPkGuildRow>, Error> {
     Ok(sqlx::query_as!(
         ModPkGuildRow,
-        "SELECT guild_id, user_id, system_id, token FROM mod_pk_guilds",
+        "SELECT guild_id, user_id, system_id, token, assign_fronter_roles, role_name_template, role_marker, role_icons, role_hoist, role_order, role_order_group, member_group, verified, webhook_token, private_members FROM mod_pk_guilds",
     )
     .fetch_all(db)
     .await?)
 }
+
+// systems in any guild that receive updates through dispatch webhooks
+pub(crate) async fn get_dispatch_systems(
+    db: &sqlx::PgPool,
+    system_uuid: Uuid,
+) -> Result<Vec<ModPkGuildRow>, Error> {
+    Ok(sqlx::query_as!(
+        ModPkGuildRow,
+        "SELECT guild_id, user_id, system_id, token, assign_fronter_roles, role_name_template, role_marker, role_icons, role_hoist, role_order, role_order_group, member_group, verified, webhook_token, private_members FROM mod_pk_guilds WHERE system_uuid = $1 AND webhook_token IS NOT NULL",
+        system_uuid,
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+pub(crate) async fn save_assign_fronter_roles(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    enabled: bool,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET assign_fronter_roles = $3 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        enabled,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_role_name_template(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    template: &str,
+    marker: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET role_name_template = $3, role_marker = $4 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        template,
+        marker,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_role_attributes(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    icons: bool,
+    hoist: bool,
+    order: &str,
+    order_group: Option<String>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET role_icons = $3, role_hoist = $4, role_order = $5, role_order_group = $6 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        icons,
+        hoist,
+        order,
+        order_group,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_member_group(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    member_group: Option<String>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET member_group = $3 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        member_group,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_private_members(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    private_members: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET private_members = $3 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        private_members,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn clear_token(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET token = NULL WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_guild_settings(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_guilds WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+// everything stored about a guild, for when tulpje gets removed from it
+pub(crate) async fn delete_guild_data(db: &sqlx::PgPool, guild_id: u64) -> Result<(), Error> {
+    let guild_id = i64::try_from(guild_id)?;
+    let mut tx = db.begin().await?;
+
+    sqlx::query!("DELETE FROM mod_pk_switches WHERE guild_id = $1", guild_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM mod_pk_switch_logs WHERE guild_id = $1",
+        guild_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM mod_pk_fronter_channels WHERE guild_id = $1",
+        guild_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM mod_pk_fronters WHERE guild_id = $1", guild_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM mod_pk_roles WHERE guild_id = $1", guild_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM mod_pk_role_sync WHERE guild_id = $1", guild_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM mod_pk_guilds WHERE guild_id = $1", guild_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// (guild_id, system_id, token)
+pub(crate) async fn get_tokens(
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<(u64, String, String)>, Error> {
+    let result = sqlx::query!(
+        "SELECT guild_id, system_id, token FROM mod_pk_guilds WHERE token IS NOT NULL"
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(result
+        .into_iter()
+        .filter_map(|row| Some((row.guild_id.try_into().unwrap(), row.system_id, row.token?)))
+        .collect())
+}
+
+pub(crate) async fn save_token(
+    db: impl sqlx::PgExecutor<'_>,
+    guild_id: u64,
+    system_id: &str,
+    token: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET token = $3 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        token,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_webhook_token(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    system_uuid: Uuid,
+    webhook_token: Option<String>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_guilds SET system_uuid = $3, webhook_token = $4 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        system_uuid,
+        webhook_token,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
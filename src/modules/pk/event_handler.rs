@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude::{self as serenity};
+use tracing::{error, info};
+
+use super::db;
+use super::fronters::{db as fronters_db, display::forget_last_fronters, teardown};
+use super::switches::db as switches_db;
+use crate::types::{Data, Error};
+
+pub(crate) struct EventHandler {
+    pub(crate) data: Arc<Data>,
+}
+
+#[serenity::async_trait]
+impl serenity::EventHandler for EventHandler {
+    async fn guild_delete(
+        &self,
+        _ctx: serenity::Context,
+        incomplete: serenity::UnavailableGuild,
+        _full: Option<serenity::Guild>,
+    ) {
+        // unavailable guilds are an outage on discord's end, we're still in those
+        if incomplete.unavailable {
+            return;
+        }
+
+        let guild_id = incomplete.id.get();
+        if let Err(err) = db::delete_guild_data(&self.data.db, guild_id).await {
+            error!(guild_id, "error deleting PluralKit data for guild: {}", err);
+            return;
+        }
+        self.data
+            .last_fronters
+            .retain(|(guild, _), _| *guild != guild_id);
+        self.data
+            .role_sync_reports
+            .retain(|(guild, _), _| *guild != guild_id);
+
+        info!(guild_id, "removed from guild, PluralKit data deleted");
+    }
+
+    async fn channel_delete(
+        &self,
+        ctx: serenity::Context,
+        channel: serenity::GuildChannel,
+        _messages: Option<Vec<serenity::Message>>,
+    ) {
+        if let Err(err) = handle_channel_delete(&ctx, &self.data, &channel).await {
+            error!(
+                guild_id = channel.guild_id.get(),
+                channel_id = channel.id.get(),
+                "error handling deleted channel: {}",
+                err
+            );
+        }
+    }
+}
+
+async fn handle_channel_delete(
+    ctx: &serenity::Context,
+    data: &Data,
+    channel: &serenity::GuildChannel,
+) -> Result<(), Error> {
+    let (guild_id, channel_id) = (channel.guild_id, channel.id.get());
+
+    // fronters can't be shown anymore without their category or channel
+    // NOTE: discord keeps the channels of a deleted category, those are left for
+    //       the admins to clean up rather than deleting them unasked
+    for fronters in fronters_db::get_fronter_categories_for_guild(&data.db, guild_id.get())
+        .await?
+        .into_iter()
+        .filter(|f| f.channel_id == channel_id)
+    {
+        let _lock = data.lock_system(guild_id.get(), &fronters.system_id).await;
+        teardown::teardown_fronters(ctx, data, guild_id, &fronters.system_id, false).await?;
+        info!(
+            guild_id = guild_id.get(),
+            system_id = fronters.system_id,
+            "fronter channel deleted, fronters removed"
+        );
+    }
+
+    // a fronter's channel got deleted by hand, make sure it's recreated
+    for fronters in fronters_db::get_fronter_categories_for_guild(&data.db, guild_id.get()).await? {
+        let tracked =
+            fronters_db::get_fronter_channels(&data.db, guild_id.get(), &fronters.system_id)
+                .await?;
+        if tracked.iter().any(|c| c.channel_id == channel_id) {
+            fronters_db::delete_fronter_channel(&data.db, channel_id).await?;
+            // the stored state would otherwise skip the update until the fronters change
+            fronters_db::clear_last_fronters(&data.db, guild_id.get(), &fronters.system_id).await?;
+            forget_last_fronters(data, guild_id.get(), &fronters.system_id);
+        }
+    }
+
+    for log in switches_db::get_switch_logs(&data.db)
+        .await?
+        .into_iter()
+        .filter(|log| log.guild_id == guild_id.get() && log.channel_id == channel_id)
+    {
+        switches_db::delete_switch_log(&data.db, guild_id.get(), &log.system_id).await?;
+        switches_db::delete_switches(&data.db, guild_id.get(), &log.system_id).await?;
+        info!(
+            guild_id = guild_id.get(),
+            system_id = log.system_id,
+            "switch log channel deleted, switch log removed"
+        );
+    }
+
+    Ok(())
+}
@@ -1,3 +1,6 @@
 pub(crate) mod commands;
 pub(crate) mod db;
+pub(crate) mod display;
+pub(crate) mod naming;
 pub(crate) mod tasks;
+pub(crate) mod teardown;
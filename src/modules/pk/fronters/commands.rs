@@ -1,51 +1,59 @@
 use std::collections::{HashMap, HashSet};
 
-use pkrs::model::PkId;
 use poise::serenity_prelude::{self as serenity, CacheHttp};
-use serde_either::StringOrStruct;
+use sqlx::types::Uuid;
 use tracing::error;
 
-use super::db;
-use crate::modules::pk::db::{get_guild_settings_for_id, ModPkGuildRow};
-use crate::types::{Context, Error};
-use crate::util::get_member_name;
-
-async fn get_desired_fronters(system: &PkId, token: String) -> Result<HashSet<String>, Error> {
-    let pk = pkrs::client::PkClient {
-        token,
-        ..Default::default()
+use super::db::{self, ModPkFrontersRow};
+use super::display::{forget_last_fronters, DisplayFronter, FronterDisplay, FronterOrder};
+use super::naming;
+use super::tasks::update_fronters_for_guild;
+use super::teardown;
+use crate::modules::pk::api::{Fronters, PkApi};
+use crate::modules::pk::db::ModPkGuildRow;
+use crate::modules::pk::privacy::{apply_privacy, PrivateMembers};
+use crate::modules::pk::shared::{ensure_may_display, get_command_system, get_group_member_ids};
+use crate::types::{Context, Data, Error};
+
+pub(crate) async fn get_fronter_members(
+    pk: &PkApi,
+    system_id: &str,
+    token: &str,
+    member_group: Option<&str>,
+    private_members: &PrivateMembers,
+) -> Result<Fronters, Error> {
+    let group_members = match member_group {
+        Some(group) => Some(get_group_member_ids(pk, system_id, group, token).await?),
+        None => None,
     };
 
-    let fronters = pk
-        .get_system_fronters(system)
-        .await?
+    let mut fronters = pk.get_system_fronters(system_id, token).await?;
+    fronters
         .members
-        .into_iter()
-        .filter_map(|m| match m {
-            StringOrStruct::String(_) => None,
-            StringOrStruct::Struct(member) => Some(get_member_name(&member)),
-        })
-        .collect();
+        .retain(|m| group_members.as_ref().is_none_or(|g| g.contains(&m.uuid)));
+    fronters.members = apply_privacy(fronters.members, private_members);
 
     Ok(fronters)
 }
 
+// reads from the cache when possible, it's kept up to date by gateway events
 async fn get_fronter_channels(
     ctx: &serenity::Context,
     guild: serenity::GuildId,
     cat_id: serenity::ChannelId,
 ) -> Result<Vec<serenity::GuildChannel>, Error> {
-    let channels = ctx
-        .http
-        .get_guild(guild)
-        .await?
-        .channels(&ctx)
-        .await?
-        .into_values()
-        .filter(|c| c.parent_id.is_some() && c.parent_id.unwrap() == cat_id)
-        .collect();
+    let cached: Option<Vec<serenity::GuildChannel>> = guild
+        .to_guild_cached(&ctx.cache)
+        .map(|g| g.channels.values().cloned().collect());
+    let channels = match cached {
+        Some(channels) => channels,
+        None => guild.channels(&ctx).await?.into_values().collect(),
+    };
 
-    Ok(channels)
+    Ok(channels
+        .into_iter()
+        .filter(|c| c.parent_id == Some(cat_id))
+        .collect())
 }
 
 async fn get_fronter_category(
@@ -68,134 +76,524 @@ async fn get_fronter_category(
     }
 }
 
-// TODO: Instrument why this bitch slow, are we even using discord's cache?
-//       should definitely do that
+// the category's permissions, so channels in a private category stay private,
+// with nobody allowed to join them
+fn channel_permissions(
+    cat: &serenity::GuildChannel,
+    everyone: serenity::RoleId,
+) -> Vec<serenity::PermissionOverwrite> {
+    let everyone = serenity::PermissionOverwriteType::Role(everyone);
+    let mut permissions = cat.permission_overwrites.clone();
+
+    match permissions.iter_mut().find(|p| p.kind == everyone) {
+        Some(overwrite) => {
+            overwrite.allow.remove(serenity::Permissions::CONNECT);
+            overwrite.deny.insert(serenity::Permissions::CONNECT);
+        }
+        None => permissions.push(serenity::PermissionOverwrite {
+            deny: serenity::Permissions::CONNECT,
+            allow: serenity::Permissions::empty(),
+            kind: everyone,
+        }),
+    }
+
+    permissions
+}
+
 pub(crate) async fn update_fronter_channels(
     ctx: &serenity::Context,
+    data: &Data,
     guild: serenity::PartialGuild,
     gs: &ModPkGuildRow,
     cat: serenity::GuildChannel,
+    fronters: &ModPkFrontersRow,
+    desired: &[DisplayFronter],
 ) -> Result<(), Error> {
-    let fronter_channels = get_fronter_channels(ctx, guild.id, cat.id).await?;
-    let desired_fronters = get_desired_fronters(
-        &PkId(gs.system_id.clone()),
-        gs.token.clone().unwrap_or("".to_owned()),
-    )
-    .await?;
-    let current_fronters: HashSet<String> =
-        fronter_channels.iter().map(|c| c.name.to_owned()).collect();
+    let guild_id = guild.id.get();
 
-    let mut fronter_channel_map: HashMap<String, serenity::GuildChannel> = fronter_channels
-        .iter()
-        .map(|c| (c.name.to_owned(), c.to_owned()))
-        .collect();
+    let mut channels: HashMap<serenity::ChannelId, serenity::GuildChannel> =
+        get_fronter_channels(ctx, guild.id, cat.id)
+            .await?
+            .into_iter()
+            .map(|c| (c.id, c))
+            .collect();
+
+    // channels we created ourselves, by PluralKit member uuid
+    let mut current: HashMap<Uuid, serenity::GuildChannel> = HashMap::new();
+    for row in db::get_fronter_channels(&data.db, guild_id, &gs.system_id).await? {
+        match channels.remove(&serenity::ChannelId::new(row.channel_id)) {
+            Some(channel) => {
+                current.insert(row.member_uuid, channel);
+            }
+            // channel got deleted outside of tulpje, forget about it so it gets recreated
+            None => db::delete_fronter_channel(&data.db, row.channel_id).await?,
+        }
+    }
+
+    // NOTE: channels in the category we didn't create, like other systems' channels
+    //       or ones made by hand, are left alone, the exception being channels
+    //       from before they were tracked, which get adopted by their name once
+    if fronters.adopt_channels {
+        let adopted = {
+            let untracked: Vec<(serenity::ChannelId, &str)> = channels
+                .values()
+                .filter(|c| {
+                    c.kind == serenity::ChannelType::Voice && c.name.starts_with(&fronters.prefix)
+                })
+                .map(|c| (c.id, c.name.as_str()))
+                .collect();
+            adoptable_channels(desired, &current, &untracked)
+        };
+
+        for (member, id) in adopted {
+            db::save_fronter_channel(&data.db, guild_id, &gs.system_id, member, id.get()).await?;
+            current.insert(member, channels.remove(&id).unwrap());
+        }
+        db::save_channels_adopted(&data.db, guild_id, &gs.system_id).await?;
+    }
+
+    let changes = {
+        let existing: HashMap<Uuid, (&str, u16)> = current
+            .iter()
+            .map(|(member, c)| (*member, (c.name.as_str(), c.position)))
+            .collect();
+        plan_channel_changes(desired, &existing)
+    };
+
+    for (member, change) in changes {
+        match change {
+            ChannelChange::Delete => {
+                let channel = current.remove(&member).unwrap();
+                if let Err(e) = channel.delete(&ctx).await {
+                    error!("error deleting channel '{}': {}", channel.name, e);
+                    continue;
+                }
+
+                db::delete_fronter_channel(&data.db, channel.id.get()).await?;
+            }
+            ChannelChange::Create { name, position } => {
+                let permissions = channel_permissions(&cat, guild.id.everyone_role());
+
+                let channel_create = serenity::CreateChannel::new(name)
+                    .position(position)
+                    .category(cat.id)
+                    .permissions(permissions)
+                    .kind(serenity::ChannelType::Voice);
+
+                // NOTE: created and tracked in a task of its own, so the guild timeout
+                //       can't drop it in between and leave an untracked duplicate
+                let (task_ctx, db, system_id, name) = (
+                    ctx.clone(),
+                    data.db.clone(),
+                    gs.system_id.clone(),
+                    name.to_owned(),
+                );
+                let guild = guild.id;
+                tokio::spawn(async move {
+                    match guild.create_channel(&task_ctx, channel_create).await {
+                        Ok(channel) => {
+                            db::save_fronter_channel(
+                                &db,
+                                guild.get(),
+                                &system_id,
+                                member,
+                                channel.id.get(),
+                            )
+                            .await?;
+                        }
+                        Err(e) => error!("error creating fronter channel '{}': {}", name, e),
+                    }
+                    Ok::<(), Error>(())
+                })
+                .await??;
+            }
+            ChannelChange::Edit { name, position } => {
+                let channel = current.get_mut(&member).unwrap();
+
+                let mut edit = serenity::EditChannel::new().position(position);
+                if let Some(name) = name {
+                    edit = edit.name(name);
+                }
+
+                if let Err(e) = channel.edit(&ctx, edit).await {
+                    error!("error updating channel '{}': {}", channel.name, e);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// channels that can be adopted by members that don't have one, only when the
+// name is unambiguous, so members with the same name don't fight over a channel
+fn adoptable_channels<T>(
+    desired: &[DisplayFronter],
+    current: &HashMap<Uuid, T>,
+    untracked: &[(serenity::ChannelId, &str)],
+) -> Vec<(Uuid, serenity::ChannelId)> {
+    let count = |name: &str| {
+        (
+            desired.iter().filter(|f| f.name == name).count(),
+            untracked.iter().filter(|(_, n)| *n == name).count(),
+        )
+    };
 
-    let fronter_pos_map: HashMap<String, u16> = desired_fronters
+    desired
         .iter()
-        .enumerate()
-        // WARN: could this result in a panic/error? usize into u16
-        .map(|(k, v)| (v.to_owned(), k.try_into().unwrap()))
+        .filter(|f| !current.contains_key(&f.member.uuid))
+        .filter(|f| count(&f.name) == (1, 1))
+        .filter_map(|f| {
+            let (id, _) = untracked.iter().find(|(_, name)| *name == f.name)?;
+            Some((f.member.uuid, *id))
+        })
+        .collect()
+}
+
+// what needs to happen to a member's channel
+#[derive(Debug, PartialEq)]
+enum ChannelChange<'a> {
+    Create {
+        name: &'a str,
+        position: u16,
+    },
+    // name is None if it doesn't need to change
+    Edit {
+        name: Option<&'a str>,
+        position: u16,
+    },
+    Delete,
+}
+
+// `existing` has the name and position of every member's channel, positions
+// follow the order of `desired`, channels that already match are left alone as
+// renames are heavily rate limited
+fn plan_channel_changes<'a>(
+    desired: &'a [DisplayFronter],
+    existing: &HashMap<Uuid, (&str, u16)>,
+) -> Vec<(Uuid, ChannelChange<'a>)> {
+    let desired_members: HashSet<Uuid> = desired.iter().map(|f| f.member.uuid).collect();
+
+    // deletes go first, so the remaining channels end up at the right positions
+    let mut stale: Vec<Uuid> = existing
+        .keys()
+        .filter(|member| !desired_members.contains(member))
+        .copied()
         .collect();
+    stale.sort();
 
-    let delete_fronters = current_fronters.difference(&desired_fronters);
-    let create_fronters = desired_fronters.difference(&current_fronters);
+    let mut changes: Vec<(Uuid, ChannelChange<'a>)> = stale
+        .into_iter()
+        .map(|member| (member, ChannelChange::Delete))
+        .collect();
 
-    for fronter in delete_fronters {
-        let channel = fronter_channel_map.get(fronter).unwrap();
-        if let Err(e) = channel.delete(&ctx).await {
-            error!("error deleting channel '{}': {}", fronter, e);
-            continue;
+    for (pos, fronter) in desired.iter().enumerate() {
+        let (member, name) = (fronter.member.uuid, fronter.name.as_str());
+        let position = u16::try_from(pos).unwrap_or(u16::MAX);
+
+        match existing.get(&member) {
+            None => changes.push((member, ChannelChange::Create { name, position })),
+            Some((current_name, current_position))
+                if *current_name != name || *current_position != position =>
+            {
+                changes.push((
+                    member,
+                    ChannelChange::Edit {
+                        name: Some(name).filter(|name| name != current_name),
+                        position,
+                    },
+                ))
+            }
+            Some(_) => {}
         }
-
-        fronter_channel_map.remove(fronter);
     }
 
-    for fronter in create_fronters {
-        let pos = fronter_pos_map
-            .get(fronter)
-            .expect("couldn't get position for fronter, this should never happen!");
+    changes
+}
 
-        let permissions = vec![serenity::PermissionOverwrite {
-            deny: serenity::Permissions::CONNECT,
-            allow: serenity::Permissions::empty(),
-            kind: serenity::PermissionOverwriteType::Role(guild.id.everyone_role()),
-        }];
-
-        let channel_create = serenity::CreateChannel::new(fronter)
-            .position(*pos)
-            .category(cat.id)
-            .permissions(permissions)
-            .kind(serenity::ChannelType::Voice);
-
-        let channel = match guild.create_channel(&ctx, channel_create).await {
-            Ok(chan) => chan,
-            Err(e) => {
-                error!("error creating fronter channel '{}': {}", fronter, e);
-                continue;
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-fronter-display",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_fronter_display(
+    ctx: Context<'_>,
+    #[description = "where to show the fronters, use /setup-fronters for a voice channel per fronter"]
+    display: FronterDisplay,
+    #[description = "text channel for messages and topics, voice channel for channel names"]
+    #[channel_types("Text", "Voice")]
+    channel: serenity::GuildChannel,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.partial_guild().await.ok_or("couldn't fetch guild")?;
+    let db = &ctx.data().db;
+
+    let gs = get_command_system(ctx, system).await?;
+
+    if display == FronterDisplay::Channels {
+        ctx.reply("error: use /setup-fronters to show a voice channel per fronter")
+            .await?;
+        return Ok(());
+    }
+
+    if channel.kind != display.channel_kind() {
+        ctx.reply(format!(
+            "error: {} needs a {} channel",
+            display.name().to_lowercase(),
+            match display.channel_kind() {
+                serenity::ChannelType::Voice => "voice",
+                _ => "text",
             }
-        };
+        ))
+        .await?;
+        return Ok(());
+    }
 
-        fronter_channel_map.insert(fronter.to_owned(), channel.to_owned());
+    if let Err(err) = ensure_may_display(&gs, &guild, &channel) {
+        ctx.reply(format!("error: {}", err)).await?;
+        return Ok(());
     }
-    for (name, position) in fronter_pos_map.iter() {
-        let mut channel = fronter_channel_map
-            .get(name)
-            .expect("couldn't get channel from fronter_channel_map, this should never happen!")
-            .to_owned();
 
-        if channel.position == *position {
-            continue;
-        }
+    // a single channel can't show multiple systems' fronters
+    let conflict = db::get_fronter_categories_for_guild(db, guild.id.get())
+        .await?
+        .into_iter()
+        .find(|f| f.channel_id == channel.id.get() && f.system_id != gs.system_id);
+    if let Some(conflict) = conflict {
+        ctx.reply(format!(
+            "error: channel already shows the fronters of system `{}`",
+            conflict.system_id
+        ))
+        .await?;
+        return Ok(());
+    }
 
-        if let Err(e) = channel
-            .edit(&ctx, serenity::EditChannel::new().position(*position))
-            .await
-        {
-            error!("error moving channel '{}': {}", name, e);
-            continue;
+    // voice channels from before aren't needed anymore
+    let mut failed = 0;
+    if let Some(previous) = db::get_fronter_category(db, guild.id.get(), &gs.system_id).await? {
+        if previous.display == FronterDisplay::Channels.id() {
+            for row in db::get_fronter_channels(db, guild.id.get(), &gs.system_id).await? {
+                if let Err(err) = serenity::ChannelId::new(row.channel_id).delete(ctx).await {
+                    error!(
+                        guild_id = guild.id.get(),
+                        channel_id = row.channel_id,
+                        "error deleting fronter channel: {}",
+                        err
+                    );
+                    failed += 1;
+                }
+            }
+            db::delete_fronter_channels(db, guild.id.get(), &gs.system_id).await?;
         }
     }
 
+    db::save_fronter_category(
+        db,
+        guild.id.get(),
+        &gs.system_id,
+        channel.id.get(),
+        "",
+        display.id(),
+    )
+    .await?;
+    forget_last_fronters(ctx.data(), guild.id.get(), &gs.system_id);
+
+    ctx.reply(match failed {
+        0 => format!(
+            "fronters will be shown as {} in <#{}>, run /update-fronters to apply",
+            display.name().to_lowercase(),
+            channel.id
+        ),
+        failed => format!(
+            "fronters will be shown as {} in <#{}>, run /update-fronters to apply, {} old fronter channel(s) couldn't be deleted",
+            display.name().to_lowercase(),
+            channel.id,
+            failed
+        ),
+    })
+    .await?;
     Ok(())
 }
 
 #[poise::command(
     slash_command,
     guild_only = true,
-    rename = "update-fronters",
+    rename = "setup-fronter-names",
     default_member_permissions = "MANAGE_GUILD"
 )]
-pub(crate) async fn update_fronters(ctx: Context<'_>) -> Result<(), Error> {
+pub(crate) async fn setup_fronter_names(
+    ctx: Context<'_>,
+    #[description = "(optional) channel name template, e.g. `{name} (since {duration})`, leave empty for the default"]
+    template: Option<String>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
-    let guild = ctx
-        .partial_guild()
-        .await
-        .ok_or("couldn't get guild from context")?;
 
-    let guild_id = guild.id.get();
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
     let db = &ctx.data().db;
+    let gs = get_command_system(ctx, system).await?;
 
-    let cat_id = db::get_fronter_category(db, guild_id)
+    if db::get_fronter_category(db, guild_id.get(), &gs.system_id)
         .await?
-        .ok_or("fronter category not set-up, please run /setup-fronters")?;
+        .is_none()
+    {
+        ctx.reply(
+            "error: fronters not set-up, please run /setup-fronters or /setup-fronter-display",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let template = template
+        .map(|t| t.trim().to_owned())
+        .filter(|t| !t.is_empty())
+        .unwrap_or(naming::DEFAULT_TEMPLATE.to_owned());
+
+    if let Err(err) = naming::validate_template(&template) {
+        ctx.reply(format!("error: {}", err)).await?;
+        return Ok(());
+    }
+
+    db::save_name_template(db, guild_id.get(), &gs.system_id, &template).await?;
+    forget_last_fronters(ctx.data(), guild_id.get(), &gs.system_id);
+
+    ctx.reply(format!(
+        "fronter channels will be named `{}`, placeholders: {{name}}, {{display_name}}, {{pronouns}}, {{id}}, {{duration}}, run /update-fronters to apply",
+        template
+    ))
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-fronter-order",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_fronter_order(
+    ctx: Context<'_>,
+    #[description = "how to order fronters, the first fronter is the primary one in PluralKit order"]
+    order: FronterOrder,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
 
-    let gs = get_guild_settings_for_id(db, guild_id)
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let db = &ctx.data().db;
+    let gs = get_command_system(ctx, system).await?;
+
+    if db::get_fronter_category(db, guild_id.get(), &gs.system_id)
         .await?
-        .ok_or("PluralKit module not set-up, please run /setup-pk")?;
+        .is_none()
+    {
+        ctx.reply(
+            "error: fronters not set-up, please run /setup-fronters or /setup-fronter-display",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    db::save_fronter_order(db, guild_id.get(), &gs.system_id, order.id()).await?;
+    forget_last_fronters(ctx.data(), guild_id.get(), &gs.system_id);
+
+    ctx.reply(format!(
+        "fronters will be ordered by {}, run /update-fronters to apply",
+        order.name().to_lowercase()
+    ))
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "update-fronters",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn update_fronters(
+    ctx: Context<'_>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or("couldn't get guild from context")?
+        .get();
+    let db = &ctx.data().db;
 
-    let cat = ctx
-        .http()
-        .get_channel(cat_id.into())
+    let gs = get_command_system(ctx, system).await?;
+
+    let fronters = db::get_fronter_category(db, guild_id, &gs.system_id)
         .await?
-        .guild()
-        .ok_or(format!("channel {} isn't a guild channel", cat_id))?;
+        .ok_or("fronters not set-up, please run /setup-fronters or /setup-fronter-display")?;
 
-    update_fronter_channels(ctx.serenity_context(), guild, &gs, cat).await?;
+    // also re-enables updates that were disabled after failing repeatedly
+    let _lock = ctx.data().lock_system(guild_id, &gs.system_id).await;
+    update_fronters_for_guild(ctx.serenity_context(), ctx.data(), &gs, &fronters, true).await?;
 
     ctx.reply("fronter list updated!").await?;
     Ok(())
 }
 
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "teardown-fronters",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn teardown_fronters(
+    ctx: Context<'_>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let db = &ctx.data().db;
+
+    let gs = get_command_system(ctx, system).await?;
+
+    if db::get_fronter_category(db, guild_id.get(), &gs.system_id)
+        .await?
+        .is_none()
+    {
+        ctx.reply("error: fronters not set-up, nothing to tear down")
+            .await?;
+        return Ok(());
+    }
+
+    let _lock = ctx.data().lock_system(guild_id.get(), &gs.system_id).await;
+    let failed = teardown::teardown_fronters(
+        ctx.serenity_context(),
+        ctx.data(),
+        guild_id,
+        &gs.system_id,
+        true,
+    )
+    .await?;
+
+    ctx.reply(match failed {
+        0 => "fronter channels removed".into(),
+        failed => format!(
+            "fronter channels removed, {} channel(s)/message(s) couldn't be deleted",
+            failed
+        ),
+    })
+    .await?;
+    Ok(())
+}
+
 async fn create_or_get_fronter_channel(
     ctx: &serenity::Context,
     guild: &serenity::PartialGuild,
@@ -229,16 +627,224 @@ async fn create_or_get_fronter_channel(
 pub(crate) async fn setup_fronters(
     ctx: Context<'_>,
     #[description = "Name of the fronters category"] name: String,
+    #[description = "(optional) prefix for channel names, required when systems share a category"]
+    prefix: Option<String>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
 ) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
     let guild = ctx.partial_guild().await.ok_or("couldn't fetch guild")?;
+    let db = &ctx.data().db;
+
+    let gs = get_command_system(ctx, system).await?;
+    let prefix = prefix.unwrap_or_default();
+
     let fronters_category =
         create_or_get_fronter_channel(ctx.serenity_context(), &guild, name).await?;
 
+    if let Err(err) = ensure_may_display(&gs, &guild, &fronters_category) {
+        ctx.reply(format!("error: {}", err)).await?;
+        return Ok(());
+    }
+
+    // systems sharing a category need prefixes that don't overlap, otherwise
+    // nobody can tell their channels apart
+    let conflict = db::get_fronter_categories_for_guild(db, guild.id.get())
+        .await?
+        .into_iter()
+        .filter(|f| f.channel_id == fronters_category.id.get() && f.system_id != gs.system_id)
+        .find(|f| f.prefix.starts_with(&prefix) || prefix.starts_with(&f.prefix));
+    if let Some(conflict) = conflict {
+        ctx.reply(format!(
+            "error: category is already used by system `{}`{}, systems sharing a category need distinct prefixes",
+            conflict.system_id,
+            match conflict.prefix.is_empty() {
+                true => " without a prefix".into(),
+                false => format!(" with prefix `{}`", conflict.prefix),
+            }
+        ))
+        .await?;
+        return Ok(());
+    }
+
     // Save category into db
-    db::save_fronter_category(&ctx.data().db, guild.id.get(), fronters_category.id.get()).await?;
+    db::save_fronter_category(
+        db,
+        guild.id.get(),
+        &gs.system_id,
+        fronters_category.id.get(),
+        &prefix,
+        FronterDisplay::Channels.id(),
+    )
+    .await?;
+    forget_last_fronters(ctx.data(), guild.id.get(), &gs.system_id);
 
     // Inform user of success
     ctx.reply("fronter list setup!").await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    fn fronter(id: u128, name: &str) -> DisplayFronter {
+        DisplayFronter {
+            member: test_member(serde_json::json!({
+                "uuid": Uuid::from_u128(id),
+                "name": name,
+            })),
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn channel_permissions_test() {
+        let everyone = serenity::RoleId::new(1);
+        let moderators = serenity::PermissionOverwrite {
+            allow: serenity::Permissions::VIEW_CHANNEL,
+            deny: serenity::Permissions::empty(),
+            kind: serenity::PermissionOverwriteType::Role(serenity::RoleId::new(2)),
+        };
+        let mut cat: serenity::GuildChannel = serde_json::from_value(serde_json::json!({
+            "id": "10",
+            "guild_id": "1",
+            "type": 4,
+            "name": "current fronters",
+            "position": 0,
+            "permission_overwrites": [],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            channel_permissions(&cat, everyone),
+            vec![serenity::PermissionOverwrite {
+                allow: serenity::Permissions::empty(),
+                deny: serenity::Permissions::CONNECT,
+                kind: serenity::PermissionOverwriteType::Role(everyone),
+            }]
+        );
+
+        // a private category keeps its overwrites
+        cat.permission_overwrites = vec![
+            serenity::PermissionOverwrite {
+                allow: serenity::Permissions::CONNECT,
+                deny: serenity::Permissions::VIEW_CHANNEL,
+                kind: serenity::PermissionOverwriteType::Role(everyone),
+            },
+            moderators.clone(),
+        ];
+        assert_eq!(
+            channel_permissions(&cat, everyone),
+            vec![
+                serenity::PermissionOverwrite {
+                    allow: serenity::Permissions::empty(),
+                    deny: serenity::Permissions::VIEW_CHANNEL | serenity::Permissions::CONNECT,
+                    kind: serenity::PermissionOverwriteType::Role(everyone),
+                },
+                moderators,
+            ]
+        );
+    }
+
+    #[test]
+    fn adoptable_channels_test() {
+        let [a, b, c, d, e] = [1, 2, 3, 4, 5].map(serenity::ChannelId::new);
+        let desired = vec![
+            fronter(1, "A"),
+            fronter(2, "B"),
+            fronter(3, "Sam"),
+            fronter(4, "Sam"),
+            fronter(5, "C"),
+        ];
+        // B already has a channel
+        let current = HashMap::from([(Uuid::from_u128(2), ())]);
+        let untracked = vec![(a, "A"), (b, "B"), (c, "C"), (d, "C"), (e, "Sam")];
+
+        // duplicate fronter names, and duplicate channel names are ambiguous
+        assert_eq!(
+            adoptable_channels(&desired, &current, &untracked),
+            vec![(Uuid::from_u128(1), a)]
+        );
+    }
+
+    #[test]
+    fn plan_channel_changes_test() {
+        let desired = vec![fronter(1, "A"), fronter(2, "B"), fronter(3, "C")];
+        let existing = HashMap::from([
+            // already right
+            (Uuid::from_u128(1), ("A", 0)),
+            // renamed and moved down
+            (Uuid::from_u128(2), ("old", 0)),
+            // stopped fronting
+            (Uuid::from_u128(4), ("D", 2)),
+        ]);
+
+        assert_eq!(
+            plan_channel_changes(&desired, &existing),
+            vec![
+                (Uuid::from_u128(4), ChannelChange::Delete),
+                (
+                    Uuid::from_u128(2),
+                    ChannelChange::Edit {
+                        name: Some("B"),
+                        position: 1
+                    }
+                ),
+                (
+                    Uuid::from_u128(3),
+                    ChannelChange::Create {
+                        name: "C",
+                        position: 2
+                    }
+                ),
+            ]
+        );
+
+        assert!(plan_channel_changes(
+            &desired,
+            &HashMap::from([
+                (Uuid::from_u128(1), ("A", 0)),
+                (Uuid::from_u128(2), ("B", 1)),
+                (Uuid::from_u128(3), ("C", 2)),
+            ])
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn plan_channel_changes_order_test() {
+        // PluralKit's order decides positions, even if only the order changed
+        let desired = vec![fronter(2, "B"), fronter(1, "A")];
+        let existing = HashMap::from([
+            (Uuid::from_u128(1), ("A", 0)),
+            (Uuid::from_u128(2), ("B", 1)),
+        ]);
+
+        assert_eq!(
+            plan_channel_changes(&desired, &existing),
+            vec![
+                (
+                    Uuid::from_u128(2),
+                    ChannelChange::Edit {
+                        name: None,
+                        position: 0
+                    }
+                ),
+                (
+                    Uuid::from_u128(1),
+                    ChannelChange::Edit {
+                        name: None,
+                        position: 1
+                    }
+                ),
+            ]
+        );
+
+        // duplicate names are separate channels
+        let desired = vec![fronter(1, "Sam"), fronter(2, "Sam")];
+        assert_eq!(plan_channel_changes(&desired, &HashMap::new()).len(), 2);
+    }
+}
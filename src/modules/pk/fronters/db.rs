@@ -1,16 +1,36 @@
+use sqlx::types::Uuid;
+
 use crate::types::Error;
 
 pub(crate) struct ModPkFrontersRow {
     pub(crate) guild_id: u64,
-    pub(crate) category_id: u64,
+    pub(crate) system_id: String,
+    // category for voice channels, or the channel fronters are shown in otherwise
+    pub(crate) channel_id: u64,
+    // prepended to channel names, so systems can share a category
+    pub(crate) prefix: String,
+    pub(crate) name_template: String,
+    pub(crate) display: String,
+    pub(crate) fronter_order: String,
+    pub(crate) message_id: Option<u64>,
+    // what was shown last time, None if it needs updating regardless
+    pub(crate) last_fronter_uuids: Option<Vec<Uuid>>,
+    pub(crate) last_fronter_names: Option<Vec<String>>,
+    // whether channels from before they were tracked still need adopting
+    pub(crate) adopt_channels: bool,
+    // consecutive failed updates, disabled once there were too many
+    pub(crate) failures: i32,
+    pub(crate) disabled: bool,
 }
 
 pub(crate) async fn get_fronter_categories(
     db: &sqlx::PgPool,
 ) -> Result<Vec<ModPkFrontersRow>, Error> {
-    let result = sqlx::query!("SELECT guild_id, category_id FROM mod_pk_fronters")
-        .fetch_all(db)
-        .await?;
+    let result = sqlx::query!(
+        "SELECT guild_id, system_id, channel_id, prefix, name_template, display, fronter_order, message_id, last_fronter_uuids, last_fronter_names, adopt_channels, failures, disabled FROM mod_pk_fronters"
+    )
+    .fetch_all(db)
+    .await?;
 
     // TODO: Better handling of try_into()?
     //       I mean, we should actually test what happens when surpassing i64::MAX and such
@@ -18,7 +38,49 @@ pub(crate) async fn get_fronter_categories(
         .into_iter()
         .map(|row| ModPkFrontersRow {
             guild_id: row.guild_id.try_into().unwrap(),
-            category_id: row.category_id.try_into().unwrap(),
+            system_id: row.system_id,
+            channel_id: row.channel_id.try_into().unwrap(),
+            prefix: row.prefix,
+            name_template: row.name_template,
+            display: row.display,
+            fronter_order: row.fronter_order,
+            message_id: row.message_id.map(|id| id.try_into().unwrap()),
+            last_fronter_uuids: row.last_fronter_uuids,
+            last_fronter_names: row.last_fronter_names,
+            adopt_channels: row.adopt_channels,
+            failures: row.failures,
+            disabled: row.disabled,
+        })
+        .collect())
+}
+
+pub(crate) async fn get_fronter_categories_for_guild(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+) -> Result<Vec<ModPkFrontersRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT guild_id, system_id, channel_id, prefix, name_template, display, fronter_order, message_id, last_fronter_uuids, last_fronter_names, adopt_channels, failures, disabled FROM mod_pk_fronters WHERE guild_id = $1",
+        i64::try_from(guild_id)?,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|row| ModPkFrontersRow {
+            guild_id: row.guild_id.try_into().unwrap(),
+            system_id: row.system_id,
+            channel_id: row.channel_id.try_into().unwrap(),
+            prefix: row.prefix,
+            name_template: row.name_template,
+            display: row.display,
+            fronter_order: row.fronter_order,
+            message_id: row.message_id.map(|id| id.try_into().unwrap()),
+            last_fronter_uuids: row.last_fronter_uuids,
+            last_fronter_names: row.last_fronter_names,
+            adopt_channels: row.adopt_channels,
+            failures: row.failures,
+            disabled: row.disabled,
         })
         .collect())
 }
@@ -26,28 +88,251 @@ pub(crate) async fn get_fronter_categories(
 pub(crate) async fn get_fronter_category(
     db: &sqlx::PgPool,
     guild_id: u64,
-) -> Result<Option<u64>, Error> {
-    let result = sqlx::query_scalar!(
-        "SELECT category_id FROM mod_pk_fronters WHERE guild_id = $1",
+    system_id: &str,
+) -> Result<Option<ModPkFrontersRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT guild_id, system_id, channel_id, prefix, name_template, display, fronter_order, message_id, last_fronter_uuids, last_fronter_names, adopt_channels, failures, disabled FROM mod_pk_fronters WHERE guild_id = $1 AND system_id = $2",
         i64::try_from(guild_id)?,
+        system_id,
     )
     .fetch_optional(db)
     .await?;
 
-    match result {
-        Some(cat_id) => Ok(Some(cat_id.try_into()?)),
-        None => Ok(None),
-    }
+    Ok(result.map(|row| ModPkFrontersRow {
+        guild_id: row.guild_id.try_into().unwrap(),
+        system_id: row.system_id,
+        channel_id: row.channel_id.try_into().unwrap(),
+        prefix: row.prefix,
+        name_template: row.name_template,
+        display: row.display,
+        fronter_order: row.fronter_order,
+        message_id: row.message_id.map(|id| id.try_into().unwrap()),
+        last_fronter_uuids: row.last_fronter_uuids,
+        last_fronter_names: row.last_fronter_names,
+        adopt_channels: row.adopt_channels,
+        failures: row.failures,
+        disabled: row.disabled,
+    }))
 }
 
 pub(crate) async fn save_fronter_category(
     db: &sqlx::PgPool,
     guild_id: u64,
+    system_id: &str,
+    channel_id: u64,
+    prefix: &str,
+    display: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO mod_pk_fronters (guild_id, system_id, channel_id, prefix, display) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (guild_id, system_id) DO UPDATE SET channel_id = $3, prefix = $4, display = $5, message_id = NULL, last_fronter_uuids = NULL, last_fronter_names = NULL, adopt_channels = false, failures = 0, disabled = false",
+        i64::try_from(guild_id)?,
+        system_id,
+        i64::try_from(channel_id)?,
+        prefix,
+        display,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_fronter_message(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    message_id: u64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_fronters SET message_id = $3 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        i64::try_from(message_id)?,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_fronter_order(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    fronter_order: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_fronters SET fronter_order = $3, last_fronter_uuids = NULL, last_fronter_names = NULL WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        fronter_order,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_channels_adopted(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_fronters SET adopt_channels = false WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_fronter_failures(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    failures: i32,
+    disabled: bool,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_fronters SET failures = $3, disabled = $4 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        failures,
+        disabled,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_last_fronters(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    uuids: &[Uuid],
+    names: &[String],
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_fronters SET last_fronter_uuids = $3, last_fronter_names = $4 WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        uuids,
+        names,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+// makes the next update redo the fronters, even if they didn't change
+pub(crate) async fn clear_last_fronters(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_fronters SET last_fronter_uuids = NULL, last_fronter_names = NULL WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_fronter_category(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_fronters WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn save_name_template(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    name_template: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE mod_pk_fronters SET name_template = $3, last_fronter_uuids = NULL, last_fronter_names = NULL WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+        name_template,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) struct ModPkFronterChannelsRow {
+    pub(crate) member_uuid: Uuid,
+    pub(crate) channel_id: u64,
+}
+
+pub(crate) async fn get_fronter_channels(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<Vec<ModPkFronterChannelsRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT member_uuid, channel_id FROM mod_pk_fronter_channels WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|row| ModPkFronterChannelsRow {
+            member_uuid: row.member_uuid,
+            channel_id: row.channel_id.try_into().unwrap(),
+        })
+        .collect())
+}
+
+pub(crate) async fn save_fronter_channel(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    member_uuid: Uuid,
     channel_id: u64,
 ) -> Result<(), Error> {
     sqlx::query!(
-        "INSERT INTO mod_pk_fronters (guild_id, category_id) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET category_id = $2",
+        "INSERT INTO mod_pk_fronter_channels (guild_id, system_id, member_uuid, channel_id) VALUES ($1, $2, $3, $4) ON CONFLICT (guild_id, system_id, member_uuid) DO UPDATE SET channel_id = $4",
         i64::try_from(guild_id)?,
+        system_id,
+        member_uuid,
+        i64::try_from(channel_id)?,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_fronter_channel(
+    db: &sqlx::PgPool,
+    channel_id: u64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_fronter_channels WHERE channel_id = $1",
         i64::try_from(channel_id)?,
     )
     .execute(db)
@@ -55,8 +340,25 @@ pub(crate) async fn save_fronter_category(
 
     Ok(())
 }
+
+pub(crate) async fn delete_fronter_channels(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_fronter_channels WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
 pub(crate) async fn get_system_count(db: &sqlx::PgPool) -> Result<usize, Error> {
-    let system_count = sqlx::query_scalar!("SELECT COUNT(DISTINCT system_id) FROM mod_pk_fronters INNER JOIN mod_pk_guilds ON mod_pk_fronters.guild_id = mod_pk_guilds.guild_id")
+    let system_count = sqlx::query_scalar!("SELECT COUNT(DISTINCT system_id) FROM mod_pk_fronters")
         .fetch_one(db)
         .await?;
 
@@ -0,0 +1,623 @@
+use std::collections::HashMap;
+
+use pkrs::model::Member;
+use poise::serenity_prelude::{self as serenity};
+use sqlx::types::{
+    chrono::{DateTime, Utc},
+    Uuid,
+};
+use tracing::{debug, error};
+
+use super::commands::{get_fronter_members, update_fronter_channels};
+use super::db::{self, ModPkFrontersRow};
+use super::naming;
+use crate::modules::pk::db::ModPkGuildRow;
+use crate::modules::pk::privacy::PrivateMembers;
+use crate::modules::pk::shared::ensure_may_display;
+use crate::modules::pk::switches::{history::fronting_since, stats::from_pk_switches};
+use crate::types::{Data, Error};
+use crate::util::hex_to_color;
+
+// discord doesn't allow more embeds in a single message
+const MAX_EMBEDS: usize = 10;
+// discord doesn't allow channel topics longer than this
+const MAX_TOPIC_LENGTH: usize = 1024;
+
+#[derive(Debug, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum FronterDisplay {
+    #[name = "Voice Channel per Fronter"]
+    Channels,
+    #[name = "Message"]
+    Message,
+    #[name = "Channel Topic"]
+    Topic,
+    #[name = "Channel Name"]
+    ChannelName,
+}
+
+impl FronterDisplay {
+    // alias poise::ChoiceParameter::name to avoid extra imports
+    pub(crate) fn name(&self) -> &'static str {
+        poise::ChoiceParameter::name(self)
+    }
+
+    pub(crate) fn id(&self) -> &'static str {
+        match self {
+            Self::Channels => "channels",
+            Self::Message => "message",
+            Self::Topic => "topic",
+            Self::ChannelName => "channel_name",
+        }
+    }
+
+    pub(crate) fn try_from_string(string: &str) -> Result<Self, Error> {
+        match string {
+            "channels" => Ok(Self::Channels),
+            "message" => Ok(Self::Message),
+            "topic" => Ok(Self::Topic),
+            "channel_name" => Ok(Self::ChannelName),
+            _ => Err(format!("unknown fronter display {}", string).into()),
+        }
+    }
+
+    // kind of channel fronters are shown in
+    pub(crate) fn channel_kind(&self) -> serenity::ChannelType {
+        match self {
+            Self::Channels => serenity::ChannelType::Category,
+            Self::Message | Self::Topic => serenity::ChannelType::Text,
+            Self::ChannelName => serenity::ChannelType::Voice,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum FronterOrder {
+    #[name = "PluralKit Order"]
+    PluralKit,
+    #[name = "Alphabetical"]
+    Alphabetical,
+    #[name = "Longest Fronting First"]
+    LongestFronting,
+}
+
+impl FronterOrder {
+    // alias poise::ChoiceParameter::name to avoid extra imports
+    pub(crate) fn name(&self) -> &'static str {
+        poise::ChoiceParameter::name(self)
+    }
+
+    pub(crate) fn id(&self) -> &'static str {
+        match self {
+            Self::PluralKit => "pk_order",
+            Self::Alphabetical => "alphabetical",
+            Self::LongestFronting => "longest",
+        }
+    }
+
+    pub(crate) fn try_from_string(string: &str) -> Result<Self, Error> {
+        match string {
+            "pk_order" => Ok(Self::PluralKit),
+            "alphabetical" => Ok(Self::Alphabetical),
+            "longest" => Ok(Self::LongestFronting),
+            _ => Err(format!("unknown fronter order {}", string).into()),
+        }
+    }
+}
+
+// a current fronter, and the name they should be shown with
+pub(crate) struct DisplayFronter {
+    pub(crate) member: Member,
+    pub(crate) name: String,
+}
+
+// fronters come in the order PluralKit lists them, the first one being the
+// primary fronter, sorts are stable so that's the tie breaker for the others
+// NOTE: members missing from `since` are sorted last when ordering by front time
+fn order_fronters(
+    fronters: &mut [DisplayFronter],
+    order: &FronterOrder,
+    since: &HashMap<Uuid, DateTime<Utc>>,
+) {
+    match order {
+        FronterOrder::PluralKit => {}
+        FronterOrder::Alphabetical => {
+            fronters.sort_by_key(|f| f.name.to_lowercase());
+        }
+        FronterOrder::LongestFronting => {
+            fronters.sort_by_key(|f| {
+                since
+                    .get(&f.member.uuid)
+                    .copied()
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC)
+            });
+        }
+    }
+}
+
+// current fronters in the configured order
+async fn get_display_fronters(
+    data: &Data,
+    gs: &ModPkGuildRow,
+    fronters: &ModPkFrontersRow,
+    prefix: &str,
+) -> Result<Vec<DisplayFronter>, Error> {
+    let token = data.pk_tokens.decrypt_stored(&gs.token)?;
+    let current = get_fronter_members(
+        &data.pk,
+        &gs.system_id,
+        &token,
+        gs.member_group.as_deref(),
+        &PrivateMembers::try_from_string(&gs.private_members)?,
+    )
+    .await?;
+
+    // when each member started fronting, only needed for ordering and durations
+    let order = FronterOrder::try_from_string(&fronters.fronter_order)?;
+    let since: HashMap<Uuid, DateTime<Utc>> = if order == FronterOrder::LongestFronting
+        || naming::uses_duration(&fronters.name_template)
+    {
+        let (history, _) = from_pk_switches(
+            data.pk.get_latest_switches(&gs.system_id, &token).await?,
+            &current.members,
+        );
+        current
+            .members
+            .iter()
+            .filter_map(|m| Some((m.uuid, fronting_since(&history, m.uuid)?)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let now = Utc::now();
+
+    let mut desired = current
+        .members
+        .into_iter()
+        .map(|member| {
+            // NOTE: members that aren't in the fetched history fall back to the latest switch
+            let fronting_secs = since
+                .get(&member.uuid)
+                .copied()
+                .or(current.since)
+                .map(|since| (now - since).num_seconds().max(0) as u64)
+                .unwrap_or(0);
+            let name = naming::render_channel_name(
+                &fronters.name_template,
+                prefix,
+                &member,
+                fronting_secs,
+            )?;
+            Ok(DisplayFronter { member, name })
+        })
+        .collect::<Result<Vec<DisplayFronter>, String>>()?;
+
+    order_fronters(&mut desired, &order, &since);
+    Ok(desired)
+}
+
+// what was shown last, so discord only gets touched when something changed
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FronterState {
+    // where and how the fronters are shown
+    channel_id: u64,
+    display: String,
+    members: Vec<Uuid>,
+    // names include the prefix and template, so changing those counts as well
+    names: Vec<String>,
+}
+
+impl FronterState {
+    fn new(fronters: &ModPkFrontersRow, desired: &[DisplayFronter]) -> Self {
+        Self {
+            channel_id: fronters.channel_id,
+            display: fronters.display.clone(),
+            members: desired.iter().map(|f| f.member.uuid).collect(),
+            names: desired.iter().map(|f| f.name.clone()).collect(),
+        }
+    }
+
+    // state saved in the database, used after restarts
+    fn stored(fronters: &ModPkFrontersRow) -> Option<Self> {
+        Some(Self {
+            channel_id: fronters.channel_id,
+            display: fronters.display.clone(),
+            members: fronters.last_fronter_uuids.clone()?,
+            names: fronters.last_fronter_names.clone()?,
+        })
+    }
+}
+
+// NOTE: the database state is reset when saving fronter settings, this makes sure
+//       the next update isn't skipped because of what's in memory
+pub(crate) fn forget_last_fronters(data: &Data, guild_id: u64, system_id: &str) {
+    data.last_fronters.remove(&(guild_id, system_id.to_owned()));
+}
+
+fn summary(fronters: &[DisplayFronter]) -> String {
+    match fronters.is_empty() {
+        true => "Nobody is fronting".into(),
+        false => format!(
+            "Fronting: {}",
+            fronters
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ),
+    }
+}
+
+// returns whether discord was updated, unless `force` is set that only happens
+// when the fronters changed since the last update
+// NOTE: changes that don't affect names, like avatars, are only picked up when
+//       forced, deleted channels reset the state in the event handler
+pub(crate) async fn update_fronter_display(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+    fronters: &ModPkFrontersRow,
+    force: bool,
+) -> Result<bool, Error> {
+    let display = FronterDisplay::try_from_string(&fronters.display)?;
+    let prefix = match display {
+        FronterDisplay::Channels => fronters.prefix.as_str(),
+        _ => "",
+    };
+    let desired = get_display_fronters(data, gs, fronters, prefix).await?;
+
+    let key = (fronters.guild_id, fronters.system_id.clone());
+    let state = FronterState::new(fronters, &desired);
+    if !force {
+        let last = match data.last_fronters.get(&key) {
+            Some(last) => Some(last.clone()),
+            None => FronterState::stored(fronters),
+        };
+
+        if last.as_ref() == Some(&state) {
+            debug!(
+                guild_id = fronters.guild_id,
+                system_id = fronters.system_id,
+                "fronters unchanged"
+            );
+            return Ok(false);
+        }
+    }
+
+    let guild = serenity::GuildId::new(fronters.guild_id)
+        .to_partial_guild(ctx)
+        .await?;
+    let channel = serenity::ChannelId::new(fronters.channel_id)
+        .to_channel(ctx)
+        .await
+        .map_err(|err| {
+            format!(
+                "couldn't find fronter channel {}: {}",
+                fronters.channel_id, err
+            )
+        })?
+        .guild()
+        .ok_or(format!(
+            "channel {} isn't a guild channel",
+            fronters.channel_id
+        ))?;
+
+    ensure_may_display(gs, &guild, &channel)?;
+
+    match display {
+        FronterDisplay::Channels => {
+            update_fronter_channels(ctx, data, guild, gs, channel, fronters, &desired).await?
+        }
+        FronterDisplay::Message => {
+            update_fronter_message(ctx, data, channel, fronters, &desired).await?
+        }
+        FronterDisplay::Topic => {
+            let topic: String = summary(&desired).chars().take(MAX_TOPIC_LENGTH).collect();
+            update_channel(
+                ctx,
+                channel,
+                |c| c.topic.as_deref() == Some(&topic),
+                |e| e.topic(&topic),
+            )
+            .await?
+        }
+        FronterDisplay::ChannelName => {
+            let name = naming::sanitise_channel_name(&summary(&desired));
+            update_channel(ctx, channel, |c| c.name == name, |e| e.name(&name)).await?
+        }
+    }
+
+    db::save_last_fronters(
+        &data.db,
+        fronters.guild_id,
+        &fronters.system_id,
+        &state.members,
+        &state.names,
+    )
+    .await?;
+    data.last_fronters.insert(key, state);
+
+    Ok(true)
+}
+
+// only edit the channel if it changed, renames and topic changes are heavily rate limited
+async fn update_channel<'a>(
+    ctx: &serenity::Context,
+    mut channel: serenity::GuildChannel,
+    unchanged: impl Fn(&serenity::GuildChannel) -> bool,
+    edit: impl Fn(serenity::EditChannel<'a>) -> serenity::EditChannel<'a>,
+) -> Result<(), Error> {
+    if unchanged(&channel) {
+        return Ok(());
+    }
+
+    channel
+        .edit(ctx, edit(serenity::EditChannel::new()))
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct FronterEmbed {
+    title: String,
+    description: Option<String>,
+    colour: u32,
+    thumbnail: Option<String>,
+}
+
+impl FronterEmbed {
+    fn matches(&self, embed: &serenity::Embed) -> bool {
+        embed.title.as_deref() == Some(&self.title)
+            && embed.description == self.description
+            && embed.colour.map(|c| c.0) == Some(self.colour)
+            && embed.thumbnail.as_ref().map(|t| t.url.as_str()) == self.thumbnail.as_deref()
+    }
+
+    fn build(&self) -> serenity::CreateEmbed {
+        let embed = serenity::CreateEmbed::new()
+            .title(&self.title)
+            .colour(self.colour);
+        let embed = match &self.description {
+            Some(description) => embed.description(description),
+            None => embed,
+        };
+        match &self.thumbnail {
+            Some(url) => embed.thumbnail(url),
+            None => embed,
+        }
+    }
+}
+
+// one embed per fronter, the ones that don't fit get listed in the last embed
+fn fronter_embeds(fronters: &[DisplayFronter]) -> Vec<FronterEmbed> {
+    if fronters.is_empty() {
+        return vec![FronterEmbed {
+            title: summary(fronters),
+            description: None,
+            colour: serenity::colours::roles::DEFAULT.0,
+            thumbnail: None,
+        }];
+    }
+
+    let shown = match fronters.len() > MAX_EMBEDS {
+        true => MAX_EMBEDS - 1,
+        false => fronters.len(),
+    };
+
+    let mut embeds: Vec<FronterEmbed> = fronters[..shown]
+        .iter()
+        .map(|f| FronterEmbed {
+            title: f.name.clone(),
+            description: f.member.pronouns.clone(),
+            colour: hex_to_color(f.member.color.clone()).0,
+            thumbnail: f.member.avatar_url.as_ref().map(|url| url.to_string()),
+        })
+        .collect();
+
+    if shown < fronters.len() {
+        embeds.push(FronterEmbed {
+            title: format!("+{} more", fronters.len() - shown),
+            description: Some(
+                fronters[shown..]
+                    .iter()
+                    .map(|f| f.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            ),
+            colour: serenity::colours::roles::DEFAULT.0,
+            thumbnail: None,
+        });
+    }
+
+    embeds
+}
+
+async fn update_fronter_message(
+    ctx: &serenity::Context,
+    data: &Data,
+    channel: serenity::GuildChannel,
+    fronters: &ModPkFrontersRow,
+    desired: &[DisplayFronter],
+) -> Result<(), Error> {
+    let embeds = fronter_embeds(desired);
+
+    let message = match fronters.message_id {
+        // message might've been deleted, in that case we send a new one
+        Some(id) => channel.message(ctx, id).await.ok(),
+        None => None,
+    };
+
+    if let Some(mut message) = message {
+        if message.embeds.len() == embeds.len()
+            && embeds
+                .iter()
+                .zip(message.embeds.iter())
+                .all(|(desired, current)| desired.matches(current))
+        {
+            return Ok(());
+        }
+
+        message
+            .edit(
+                ctx,
+                serenity::EditMessage::new().embeds(embeds.iter().map(|e| e.build()).collect()),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let message = channel
+        .send_message(
+            ctx,
+            serenity::CreateMessage::new().embeds(embeds.iter().map(|e| e.build()).collect()),
+        )
+        .await?;
+    db::save_fronter_message(
+        &data.db,
+        fronters.guild_id,
+        &fronters.system_id,
+        message.id.get(),
+    )
+    .await?;
+
+    // pinning is only for convenience, so not being allowed to isn't an error
+    if let Err(err) = message.pin(ctx).await {
+        error!(
+            guild_id = fronters.guild_id,
+            channel_id = fronters.channel_id,
+            "couldn't pin fronter message: {}",
+            err
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    fn fronter(name: &str) -> DisplayFronter {
+        DisplayFronter {
+            member: test_member(serde_json::json!({
+                "name": name,
+                "color": "ff0000",
+                "pronouns": "they/them",
+            })),
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn fronter_display_test() {
+        for display in [
+            FronterDisplay::Channels,
+            FronterDisplay::Message,
+            FronterDisplay::Topic,
+            FronterDisplay::ChannelName,
+        ] {
+            assert_eq!(
+                FronterDisplay::try_from_string(display.id()).unwrap(),
+                display
+            );
+        }
+        assert!(FronterDisplay::try_from_string("nope").is_err());
+    }
+
+    #[test]
+    fn summary_test() {
+        assert_eq!(summary(&[]), "Nobody is fronting");
+        assert_eq!(
+            summary(&[fronter("Alex"), fronter("Sam")]),
+            "Fronting: Alex, Sam"
+        );
+    }
+
+    #[test]
+    fn order_fronters_test() {
+        let names = |fronters: &[DisplayFronter]| -> Vec<String> {
+            fronters.iter().map(|f| f.name.clone()).collect()
+        };
+        let mut fronters = vec![fronter("sam"), fronter("Alex"), fronter("kim")];
+        for (idx, f) in fronters.iter_mut().enumerate() {
+            f.member.uuid = Uuid::from_u128(idx as u128);
+        }
+
+        // Alex has been fronting longest, kim's front time is unknown
+        let since = HashMap::from([
+            (
+                Uuid::from_u128(0),
+                DateTime::from_timestamp(200, 0).unwrap(),
+            ),
+            (
+                Uuid::from_u128(1),
+                DateTime::from_timestamp(100, 0).unwrap(),
+            ),
+        ]);
+
+        order_fronters(&mut fronters, &FronterOrder::PluralKit, &since);
+        assert_eq!(names(&fronters), vec!["sam", "Alex", "kim"]);
+
+        order_fronters(&mut fronters, &FronterOrder::LongestFronting, &since);
+        assert_eq!(names(&fronters), vec!["Alex", "sam", "kim"]);
+
+        order_fronters(&mut fronters, &FronterOrder::Alphabetical, &since);
+        assert_eq!(names(&fronters), vec!["Alex", "kim", "sam"]);
+    }
+
+    #[test]
+    fn fronter_state_test() {
+        let mut row = ModPkFrontersRow {
+            guild_id: 1,
+            system_id: "abcde".into(),
+            channel_id: 2,
+            prefix: "".into(),
+            name_template: naming::DEFAULT_TEMPLATE.into(),
+            display: FronterDisplay::Message.id().into(),
+            fronter_order: FronterOrder::PluralKit.id().into(),
+            message_id: None,
+            last_fronter_uuids: None,
+            last_fronter_names: None,
+            adopt_channels: false,
+            failures: 0,
+            disabled: false,
+        };
+        let state = FronterState::new(&row, &[fronter("Alex")]);
+
+        // nothing stored yet, or reset after changing settings
+        assert_eq!(FronterState::stored(&row), None);
+
+        row.last_fronter_uuids = Some(vec![Uuid::nil()]);
+        row.last_fronter_names = Some(vec!["Alex".into()]);
+        assert_eq!(FronterState::stored(&row), Some(state.clone()));
+
+        // renamed fronters and moved displays need updating too
+        assert_ne!(FronterState::new(&row, &[fronter("Sam")]), state);
+        row.channel_id = 3;
+        assert_ne!(FronterState::stored(&row), Some(state));
+    }
+
+    #[test]
+    fn fronter_embeds_test() {
+        let embeds = fronter_embeds(&[]);
+        assert_eq!(embeds.len(), 1);
+        assert_eq!(embeds[0].title, "Nobody is fronting");
+
+        let embeds = fronter_embeds(&[fronter("Alex")]);
+        assert_eq!(
+            embeds,
+            vec![FronterEmbed {
+                title: "Alex".into(),
+                description: Some("they/them".into()),
+                colour: 0xff0000,
+                thumbnail: None,
+            }]
+        );
+
+        let fronters: Vec<DisplayFronter> = (0..12).map(|i| fronter(&format!("m{}", i))).collect();
+        let embeds = fronter_embeds(&fronters);
+        assert_eq!(embeds.len(), MAX_EMBEDS);
+        assert_eq!(embeds[9].title, "+3 more");
+        assert_eq!(embeds[9].description.as_deref(), Some("m9, m10, m11"));
+    }
+}
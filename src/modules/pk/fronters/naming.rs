@@ -0,0 +1,171 @@
+use pkrs::model::Member;
+
+use crate::modules::pk::template::{parse_template, strip_pronouns, Part};
+use crate::util::{format_significant_duration, get_member_name};
+
+// discord doesn't allow channel names longer than this
+pub(crate) const MAX_CHANNEL_NAME_LENGTH: usize = 100;
+
+// matches what fronter channels were named before templates existed
+pub(crate) const DEFAULT_TEMPLATE: &str = "{display_name}";
+
+// used when a name is left empty, the member id could identify private members
+const FALLBACK_NAME: &str = "unnamed member";
+
+const PLACEHOLDERS: [&str; 5] = ["name", "display_name", "pronouns", "id", "duration"];
+
+// discord only allows renaming a channel twice every 10 minutes, so the front
+// duration only changes that often
+const DURATION_STEP: u64 = 10 * 60;
+
+fn format_duration(secs: u64) -> String {
+    if secs < DURATION_STEP {
+        return "<10m".into();
+    }
+
+    // seconds are always 0 after rounding, so leave them out
+    format_significant_duration(secs - secs % DURATION_STEP)
+        .trim_end_matches(" 0s")
+        .to_owned()
+}
+
+// discord doesn't allow control characters or names consisting of only whitespace
+pub(crate) fn sanitise_channel_name(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+        .take(MAX_CHANNEL_NAME_LENGTH)
+        .collect::<String>()
+        .trim_end()
+        .to_owned()
+}
+
+// `fronting_secs` is how long the member has been fronting for
+pub(crate) fn render_channel_name(
+    template: &str,
+    prefix: &str,
+    member: &Member,
+    fronting_secs: u64,
+) -> Result<String, String> {
+    let display_name = get_member_name(member);
+    let duration = format_duration(fronting_secs);
+
+    let rendered = parse_template(template, &PLACEHOLDERS)?
+        .into_iter()
+        .map(|part| match part {
+            Part::Literal(text) => text,
+            Part::Placeholder("name") => strip_pronouns(&display_name),
+            Part::Placeholder("display_name") => &display_name,
+            Part::Placeholder("pronouns") => member.pronouns.as_deref().unwrap_or(""),
+            Part::Placeholder("id") => &member.id.0,
+            Part::Placeholder("duration") => &duration,
+            Part::Placeholder(_) => unreachable!("placeholders are validated when parsing"),
+        })
+        .collect::<String>();
+
+    // fall back to a fixed name so the channel always has a usable name
+    let name = match sanitise_channel_name(&rendered) {
+        name if name.is_empty() => FALLBACK_NAME.into(),
+        name => name,
+    };
+
+    Ok(sanitise_channel_name(&format!("{}{}", prefix, name)))
+}
+
+// durations need the switch history, which is only fetched when it's used
+pub(crate) fn uses_duration(template: &str) -> bool {
+    parse_template(template, &PLACEHOLDERS)
+        .is_ok_and(|parts| parts.contains(&Part::Placeholder("duration")))
+}
+
+pub(crate) fn validate_template(template: &str) -> Result<(), String> {
+    let parts = parse_template(template, &PLACEHOLDERS)?;
+
+    if !parts
+        .iter()
+        .any(|p| matches!(p, Part::Placeholder(p) if *p != "duration"))
+    {
+        return Err("template needs at least one name placeholder".into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    fn member(name: &str, display_name: Option<&str>, pronouns: Option<&str>) -> Member {
+        test_member(serde_json::json!({
+            "name": name,
+            "display_name": display_name,
+            "pronouns": pronouns,
+        }))
+    }
+
+    #[test]
+    fn format_duration_test() {
+        assert_eq!(format_duration(0), "<10m");
+        assert_eq!(format_duration(9 * 60 + 59), "<10m");
+        assert_eq!(format_duration(25 * 60), "20m");
+        assert_eq!(format_duration(2 * 3_600 + 35 * 60), "2h 30m");
+        assert_eq!(format_duration(3 * 86_400 + 5 * 3_600), "3d 5h");
+    }
+
+    #[test]
+    fn render_channel_name_test() {
+        let m = member("foo", Some("Foo (she/her)"), Some("she/her"));
+        assert_eq!(
+            render_channel_name(DEFAULT_TEMPLATE, "", &m, 0).unwrap(),
+            "Foo (she/her)"
+        );
+        assert_eq!(
+            render_channel_name("🟢 {name} · {pronouns}", "", &m, 0).unwrap(),
+            "🟢 Foo · she/her"
+        );
+        assert_eq!(
+            render_channel_name("{name} (since {duration})", "a: ", &m, 3_600).unwrap(),
+            "a: Foo (since 1h 0m)"
+        );
+
+        // empty placeholders shouldn't leave stray whitespace
+        let m = member("foo", None, None);
+        assert_eq!(
+            render_channel_name("{name}  {pronouns}\n", "", &m, 0).unwrap(),
+            "foo"
+        );
+        assert_eq!(
+            render_channel_name("{pronouns}", "a: ", &m, 0).unwrap(),
+            "a: unnamed member"
+        );
+
+        let m = member(&"a".repeat(120), None, None);
+        assert_eq!(
+            render_channel_name("{name}", "", &m, 0)
+                .unwrap()
+                .chars()
+                .count(),
+            MAX_CHANNEL_NAME_LENGTH
+        );
+
+        assert!(render_channel_name("{nope}", "", &m, 0).is_err());
+    }
+
+    #[test]
+    fn validate_template_test() {
+        assert!(validate_template("{name} (since {duration})").is_ok());
+        assert!(validate_template("{duration}").is_err());
+        assert!(validate_template("no placeholder").is_err());
+        assert!(validate_template("{name").is_err());
+    }
+
+    #[test]
+    fn uses_duration_test() {
+        assert!(uses_duration("{name} (since {duration})"));
+        assert!(!uses_duration("{name} (duration)"));
+        assert!(!uses_duration("{name}"));
+    }
+}
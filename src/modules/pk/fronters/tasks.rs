@@ -1,76 +1,257 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use futures::StreamExt;
 use poise::serenity_prelude::{self as serenity};
 use tracing::{error, info, warn};
 
 use crate::modules::pk;
+use crate::modules::pk::api::ApiError;
+use crate::modules::pk::roles::db as roles_db;
+use crate::modules::pk::shared::receives_dispatch;
 use crate::types::{Data, Error};
 
 use self::pk::db::ModPkGuildRow;
 
-use super::db::ModPkFrontersRow;
+use super::db::{self, ModPkFrontersRow};
+
+// guilds updated at the same time, so one slow guild doesn't hold up the rest
+const MAX_CONCURRENT_GUILDS: usize = 8;
+// a guild taking longer than this is most likely stuck waiting on a rate limit,
+// it'll be retried on the next run
+const GUILD_TIMEOUT: Duration = Duration::from_secs(45);
+// updates are disabled after failing this many times in a row, until they're
+// set up again or updated by hand
+const MAX_CONSECUTIVE_FAILURES: i32 = 30;
 
 pub(crate) async fn update_fronters(ctx: &serenity::Context, data: Arc<Data>) -> Result<(), Error> {
+    update_fronters_where(ctx, &data, |gs| !receives_dispatch(&data, gs)).await
+}
+
+// fallback for systems that receive dispatch webhooks, in case one got lost
+pub(crate) async fn update_dispatch_fronters(
+    ctx: &serenity::Context,
+    data: Arc<Data>,
+) -> Result<(), Error> {
+    update_fronters_where(ctx, &data, |gs| receives_dispatch(&data, gs)).await
+}
+
+async fn update_fronters_where(
+    ctx: &serenity::Context,
+    data: &Data,
+    filter: impl Fn(&ModPkGuildRow) -> bool,
+) -> Result<(), Error> {
     let fronter_cats = super::db::get_fronter_categories(&data.db).await?;
     let guild_settings = pk::db::get_guild_settings(&data.db).await?;
 
+    // systems in a guild are updated one after the other, they might share a category
+    let mut guilds: HashMap<u64, Vec<(&ModPkGuildRow, ModPkFrontersRow)>> = HashMap::new();
     for cat in fronter_cats {
-        let cur_guild_settings = guild_settings
-            .iter()
-            .find(|gs| u64::try_from(gs.guild_id).unwrap() == cat.guild_id);
+        let cur_guild_settings = guild_settings.iter().find(|gs| {
+            u64::try_from(gs.guild_id).unwrap() == cat.guild_id && gs.system_id == cat.system_id
+        });
 
         if let Some(gs) = cur_guild_settings {
-            if let Err(err) = update_fronters_for_guild(ctx, gs, &cat).await {
-                error!(guild_id = cat.guild_id, category_id = cat.category_id, err);
+            if filter(gs) && !cat.disabled {
+                guilds.entry(cat.guild_id).or_default().push((gs, cat));
             }
         } else {
             warn!(
                 guild_id = cat.guild_id,
-                "couldn't find guild settings for guild"
+                system_id = cat.system_id,
+                "couldn't find guild settings for system"
             );
         }
     }
 
+    futures::stream::iter(guilds)
+        .for_each_concurrent(MAX_CONCURRENT_GUILDS, |(guild_id, systems)| async move {
+            let started = Instant::now();
+            let timed_out = tokio::time::timeout(GUILD_TIMEOUT, update_guild(ctx, data, &systems))
+                .await
+                .is_err();
+            data.stats
+                .record_fronter_update(guild_id, started.elapsed(), timed_out);
+
+            if timed_out {
+                warn!(
+                    guild_id,
+                    "updating fronters timed out after {}s",
+                    GUILD_TIMEOUT.as_secs()
+                );
+            }
+        })
+        .await;
+
     Ok(())
 }
 
-async fn update_fronters_for_guild(
+async fn update_guild(
+    ctx: &serenity::Context,
+    data: &Data,
+    systems: &[(&ModPkGuildRow, ModPkFrontersRow)],
+) {
+    for (gs, cat) in systems {
+        let _lock = data.lock_system(cat.guild_id, &cat.system_id).await;
+        if let Err(err) = update_fronters_for_guild(ctx, data, gs, cat, false).await {
+            error!(
+                guild_id = cat.guild_id,
+                system_id = cat.system_id,
+                channel_id = cat.channel_id,
+                err
+            );
+        }
+    }
+}
+
+pub(crate) async fn update_fronters_for_guild(
     ctx: &serenity::Context,
+    data: &Data,
     gs: &ModPkGuildRow,
-    cat: &ModPkFrontersRow,
+    fronters: &ModPkFrontersRow,
+    force: bool,
 ) -> Result<(), Error> {
-    let guild = ctx.http.get_guild(cat.guild_id.into()).await?;
-
-    let cat = ctx
-        .http
-        .get_channel(cat.category_id.into())
-        .await
-        .map_err(|err| {
-            format!(
-                "couldn't find category for guild '{}' ({}) {}",
-                guild.name, guild.id, err
-            )
-        })?
-        .guild()
-        .ok_or(format!(
-            "channel {} for guild '{}' ({}) isn't a guild channel",
-            cat.category_id, guild.name, guild.id
-        ))?;
-
-    super::commands::update_fronter_channels(ctx, guild.clone(), gs, cat)
-        .await
-        .map_err(|err| {
-            format!(
-                "error updating fronters for {} ({}): {}",
-                guild.name, guild.id, err
+    let updated = match super::display::update_fronter_display(ctx, data, gs, fronters, force).await
+    {
+        Ok(updated) => updated,
+        Err(err) => {
+            if !is_outage(&err) {
+                record_failure(ctx, data, gs, fronters, &err).await?;
+            }
+
+            return Err(format!(
+                "error updating fronters for guild {} system {}: {}",
+                fronters.guild_id, gs.system_id, err
             )
-        })?;
+            .into());
+        }
+    };
+
+    if fronters.failures > 0 || fronters.disabled {
+        db::save_fronter_failures(&data.db, fronters.guild_id, &fronters.system_id, 0, false)
+            .await?;
+    }
+
+    if !updated {
+        return Ok(());
+    }
 
     info!(
-        guild.id = guild.id.get(),
-        guild.name = guild.name,
+        guild.id = fronters.guild_id,
+        system_id = gs.system_id,
         "fronters updated"
     );
 
     Ok(())
 }
+
+// PluralKit or discord having issues isn't a problem with the guild's setup
+fn is_outage(err: &Error) -> bool {
+    if matches!(err.downcast_ref(), Some(ApiError::Unavailable(_))) {
+        return true;
+    }
+
+    match err.downcast_ref::<serenity::Error>() {
+        Some(serenity::Error::Http(serenity::HttpError::UnsuccessfulRequest(response))) => {
+            response.status_code.is_server_error()
+        }
+        // timeouts and connection errors
+        Some(serenity::Error::Http(serenity::HttpError::Request(_))) => true,
+        Some(serenity::Error::Gateway(_) | serenity::Error::Io(_)) => true,
+        _ => false,
+    }
+}
+
+async fn record_failure(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+    fronters: &ModPkFrontersRow,
+    err: &Error,
+) -> Result<(), Error> {
+    let failures = fronters.failures + 1;
+    let disabled = failures >= MAX_CONSECUTIVE_FAILURES;
+
+    db::save_fronter_failures(
+        &data.db,
+        fronters.guild_id,
+        &fronters.system_id,
+        failures,
+        disabled,
+    )
+    .await?;
+
+    if disabled && !fronters.disabled {
+        warn!(
+            guild_id = fronters.guild_id,
+            system_id = fronters.system_id,
+            "fronter updates failed {} times in a row, disabling them",
+            failures
+        );
+        notify_disabled(ctx, data, gs, failures, err).await;
+    }
+
+    Ok(())
+}
+
+// tell the guild's admin channel, or whoever set up the system when there's none
+async fn notify_disabled(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+    failures: i32,
+    err: &Error,
+) {
+    let message = format!(
+        "**Fronter updates disabled** for system `{}` after failing {} times in a row, run /update-fronters to retry once the problem is fixed, last error: {}",
+        gs.system_id, failures, err
+    );
+
+    let admin_channel = match u64::try_from(gs.guild_id) {
+        Ok(guild_id) => roles_db::get_role_sync_for_id(&data.db, guild_id)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|sync| sync.admin_channel_id),
+        Err(_) => None,
+    };
+
+    let result = match admin_channel {
+        Some(channel_id) => serenity::ChannelId::new(channel_id)
+            .say(ctx, message)
+            .await
+            .map(|_| ()),
+        None => match u64::try_from(gs.user_id) {
+            Ok(user_id) => serenity::UserId::new(user_id)
+                .direct_message(ctx, serenity::CreateMessage::new().content(message))
+                .await
+                .map(|_| ()),
+            Err(_) => return,
+        },
+    };
+
+    if let Err(send_err) = result {
+        error!(
+            guild_id = gs.guild_id,
+            system_id = gs.system_id,
+            "couldn't report disabled fronter updates: {}",
+            send_err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_outage_test() {
+        assert!(is_outage(&ApiError::Unavailable("502".into()).into()));
+        assert!(!is_outage(&ApiError::Forbidden.into()));
+        assert!(!is_outage(&"missing permissions".into()));
+        assert!(is_outage(
+            &serenity::Error::Gateway(serenity::GatewayError::NoAuthentication).into()
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+use poise::serenity_prelude::{self as serenity};
+use tracing::error;
+
+use super::db;
+use super::display::{forget_last_fronters, FronterDisplay};
+use crate::types::{Data, Error};
+
+// stop showing a system's fronters, with `cleanup` the channels and message we
+// created are deleted as well, returns how many of those couldn't be deleted
+// NOTE: channels that don't exist anymore are skipped, the category might be
+//       what got deleted in the first place
+pub(crate) async fn teardown_fronters(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: serenity::GuildId,
+    system_id: &str,
+    cleanup: bool,
+) -> Result<usize, Error> {
+    let db = &data.db;
+
+    let mut failed = 0;
+    if let Some(fronters) = db::get_fronter_category(db, guild_id.get(), system_id)
+        .await?
+        .filter(|_| cleanup)
+    {
+        let channels = guild_id.channels(ctx).await?;
+        let exists = |id: u64| channels.contains_key(&serenity::ChannelId::new(id));
+
+        // the message is ours, but channels used for topics and names aren't
+        if let Some(message_id) = fronters.message_id.filter(|_| exists(fronters.channel_id)) {
+            let channel_id = serenity::ChannelId::new(fronters.channel_id);
+            if let Err(err) = channel_id.delete_message(ctx, message_id).await {
+                error!(
+                    guild_id = guild_id.get(),
+                    message_id, "error deleting fronter message: {}", err
+                );
+                failed += 1;
+            }
+        }
+
+        if fronters.display == FronterDisplay::Channels.id() {
+            // the category stays around if other systems still use it
+            let shared = db::get_fronter_categories_for_guild(db, guild_id.get())
+                .await?
+                .iter()
+                .any(|f| f.channel_id == fronters.channel_id && f.system_id != system_id);
+
+            // delete our channels inside the category first, then the category itself,
+            // channels we didn't create are left alone
+            let channel_ids = db::get_fronter_channels(db, guild_id.get(), system_id)
+                .await?
+                .into_iter()
+                .map(|row| row.channel_id)
+                .chain(Some(fronters.channel_id).filter(|_| !shared))
+                .filter(|id| exists(*id));
+            for channel_id in channel_ids {
+                if let Err(err) = serenity::ChannelId::new(channel_id).delete(ctx).await {
+                    error!(
+                        guild_id = guild_id.get(),
+                        channel_id, "error deleting channel: {}", err
+                    );
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    db::delete_fronter_channels(db, guild_id.get(), system_id).await?;
+    db::delete_fronter_category(db, guild_id.get(), system_id).await?;
+    forget_last_fronters(data, guild_id.get(), system_id);
+
+    Ok(failed)
+}
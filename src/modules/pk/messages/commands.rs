@@ -0,0 +1,120 @@
+use pkrs::model::Message;
+use poise::serenity_prelude::{self as serenity};
+
+use crate::modules::pk::api::ApiError;
+use crate::types::{Context, Error};
+use crate::util::{get_member_name, hex_to_color, is_pk_proxy};
+
+#[poise::command(context_menu_command = "Who sent this?", guild_only = true)]
+pub(crate) async fn who_sent(ctx: Context<'_>, msg: serenity::Message) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    if !is_pk_proxy(&msg.application_id) {
+        ctx.reply("error: this message wasn't proxied by PluralKit")
+            .await?;
+        return Ok(());
+    }
+
+    let message = match ctx.data().pk.get_message(msg.id.get()).await {
+        Ok(message) => message,
+        Err(ApiError::NotFound) => {
+            ctx.reply("error: PluralKit doesn't know about this message, it might've been deleted")
+                .await?;
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut embed = serenity::CreateEmbed::new()
+        .title("Who sent this?")
+        .fields(message_fields(&message));
+    if let Some(member) = &message.member {
+        embed = embed.colour(hex_to_color(member.color.clone()));
+        if let Some(avatar_url) = &member.avatar_url {
+            embed = embed.thumbnail(avatar_url.to_string());
+        }
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+// system and member can be missing when they got deleted since
+fn message_fields(message: &Message) -> Vec<(&'static str, String, bool)> {
+    vec![
+        (
+            "System",
+            message
+                .system
+                .as_ref()
+                .map_or("*deleted*".into(), |system| match &system.name {
+                    Some(name) => format!("{} (`{}`)", name, system.id.0),
+                    None => format!("`{}`", system.id.0),
+                }),
+            true,
+        ),
+        (
+            "Member",
+            message
+                .member
+                .as_ref()
+                .map_or("*deleted*".into(), |member| {
+                    format!("{} (`{}`)", get_member_name(member), member.id.0)
+                }),
+            true,
+        ),
+        ("Account", format!("<@{}>", message.sender), true),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    #[test]
+    fn message_fields_test() {
+        let mut message: Message = serde_json::from_value(serde_json::json!({
+            "timestamp": "2025-01-04T12:00:00Z",
+            "id": "1000",
+            "original": "999",
+            "sender": "1234",
+            "channel": "10",
+            "guild": "1",
+            "system": {
+                "id": "exmpl",
+                "name": "Example",
+                "created": null,
+            },
+        }))
+        .unwrap();
+        message.member = Some(test_member(serde_json::json!({
+            "name": "foo",
+            "display_name": "Foo",
+        })));
+
+        assert_eq!(
+            message_fields(&message),
+            vec![
+                ("System", "Example (`exmpl`)".into(), true),
+                ("Member", "Foo (`abcde`)".into(), true),
+                ("Account", "<@1234>".into(), true),
+            ]
+        );
+
+        let message = Message {
+            system: None,
+            member: None,
+            ..message
+        };
+        assert_eq!(
+            message_fields(&message),
+            vec![
+                ("System", "*deleted*".into(), true),
+                ("Member", "*deleted*".into(), true),
+                ("Account", "<@1234>".into(), true),
+            ]
+        );
+    }
+}
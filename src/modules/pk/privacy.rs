@@ -0,0 +1,175 @@
+use pkrs::model::{Member, PkId, Privacy};
+
+use crate::types::Error;
+
+// shown instead of members that are hidden from the public
+pub(crate) const PLACEHOLDER_NAME: &str = "Private member";
+
+#[derive(Debug, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum PrivateMembers {
+    #[name = "Hide"]
+    Hide,
+    #[name = "Show as \"Private member\""]
+    Placeholder,
+    #[name = "Show (ignore PluralKit privacy)"]
+    Show,
+}
+
+impl PrivateMembers {
+    // alias poise::ChoiceParameter::name to avoid extra imports
+    pub(crate) fn name(&self) -> &'static str {
+        poise::ChoiceParameter::name(self)
+    }
+
+    pub(crate) fn id(&self) -> &'static str {
+        match self {
+            Self::Hide => "hide",
+            Self::Placeholder => "placeholder",
+            Self::Show => "show",
+        }
+    }
+
+    pub(crate) fn try_from_string(string: &str) -> Result<Self, Error> {
+        match string {
+            "hide" => Ok(Self::Hide),
+            "placeholder" => Ok(Self::Placeholder),
+            "show" => Ok(Self::Show),
+            _ => Err(format!("unknown private members setting {}", string).into()),
+        }
+    }
+
+    pub(crate) fn description(&self) -> &'static str {
+        match self {
+            Self::Hide => {
+                "private members, members with a private name and private fields are hidden"
+            }
+            Self::Placeholder => {
+                "private members are shown as \"Private member\" but don't get a role, private fields are hidden"
+            }
+            Self::Show => {
+                "**all members are shown, including private ones and their private fields**"
+            }
+        }
+    }
+}
+
+fn is_private(privacy: &Privacy) -> bool {
+    matches!(privacy, Privacy::Private)
+}
+
+// strip everything that could identify the member, the uuid stays so channels
+// can still be matched up, it isn't shown anywhere
+// NOTE: the short id is cleared as well, it would link the member across posts,
+//       so switches can't be matched up with this member anymore
+fn redact(member: &mut Member) {
+    member.id = PkId(String::new());
+    member.name = PLACEHOLDER_NAME.into();
+    member.display_name = None;
+    member.color = None;
+    member.birthday = None;
+    member.pronouns = None;
+    member.avatar_url = None;
+    member.webhook_avatar_url = None;
+    member.banner = None;
+    member.description = None;
+}
+
+// show members the way PluralKit shows them to anyone outside the system
+// NOTE: privacy is only included when the token belongs to the system, without
+//       one PluralKit already left out anything private
+pub(crate) fn apply_privacy(members: Vec<Member>, setting: &PrivateMembers) -> Vec<Member> {
+    if *setting == PrivateMembers::Show {
+        return members;
+    }
+
+    members
+        .into_iter()
+        .filter_map(|mut member| {
+            let Some(privacy) = member.privacy.take() else {
+                return Some(member);
+            };
+
+            // NOTE: PluralKit shows the display name in place of a private name, but
+            //       that's often just as identifying, so those are treated as private
+            if is_private(&privacy.visibility) || is_private(&privacy.name_privacy) {
+                return match setting {
+                    PrivateMembers::Placeholder => {
+                        redact(&mut member);
+                        Some(member)
+                    }
+                    _ => None,
+                };
+            }
+
+            if is_private(&privacy.description_privacy) {
+                member.description = None;
+            }
+            if is_private(&privacy.birthday_privacy) {
+                member.birthday = None;
+            }
+            if is_private(&privacy.pronoun_privacy) {
+                member.pronouns = None;
+            }
+            if is_private(&privacy.avatar_privacy) {
+                member.avatar_url = None;
+                member.webhook_avatar_url = None;
+                member.banner = None;
+            }
+
+            Some(member)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    fn member(name: &str, visibility: &str, name_privacy: &str) -> Member {
+        test_member(serde_json::json!({
+            "name": name,
+            "pronouns": "she/her",
+            "privacy": {
+                "visibility": visibility,
+                "name_privacy": name_privacy,
+                "description_privacy": "public",
+                "birthday_privacy": "public",
+                "pronoun_privacy": "private",
+                "avatar_privacy": "public",
+                "metadata_privacy": "public",
+            },
+        }))
+    }
+
+    fn names(members: &[Member]) -> Vec<&str> {
+        members.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn apply_privacy_test() {
+        let members = || {
+            vec![
+                member("public", "public", "public"),
+                member("hidden", "private", "public"),
+                member("nameless", "public", "private"),
+            ]
+        };
+
+        let hidden = apply_privacy(members(), &PrivateMembers::Hide);
+        assert_eq!(names(&hidden), vec!["public"]);
+        assert_eq!(hidden[0].pronouns, None);
+
+        let placeholder = apply_privacy(members(), &PrivateMembers::Placeholder);
+        assert_eq!(
+            names(&placeholder),
+            vec!["public", PLACEHOLDER_NAME, PLACEHOLDER_NAME]
+        );
+        assert_eq!(placeholder[1].pronouns, None);
+        assert_eq!(placeholder[1].id.0, "");
+
+        let shown = apply_privacy(members(), &PrivateMembers::Show);
+        assert_eq!(names(&shown), vec!["public", "hidden", "nameless"]);
+        assert_eq!(shown[0].pronouns.as_deref(), Some("she/her"));
+    }
+}
@@ -0,0 +1,1168 @@
+use core::panic;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use pkrs::model::Member;
+use poise::serenity_prelude::{self as serenity, PartialGuild};
+use sqlx::types::Uuid;
+use tracing::{debug, warn};
+
+use crate::modules::pk::api::PkApi;
+use crate::modules::pk::db::{self, ModPkGuildRow};
+use crate::modules::pk::fronters::commands::get_fronter_members;
+use crate::modules::pk::privacy::{apply_privacy, PrivateMembers};
+use crate::modules::pk::roles::db as roles_db;
+use crate::modules::pk::roles::naming;
+use crate::modules::pk::roles::shared::{order_members, RoleOrder};
+use crate::modules::pk::shared::{
+    confirm_changes, get_command_system, get_group_member_ids, get_system_members,
+};
+use crate::types::{Context, Data, Error};
+use crate::util::hex_to_color;
+
+// roles are visible to the whole guild, unverified systems could be anyone's
+const UNVERIFIED_ERROR: &str =
+    "system isn't verified, alter roles can't be used, verify the system with /setup-pk first";
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+struct MemberRole {
+    id: Option<serenity::RoleId>,
+    name: String,
+    color: u32,
+    hoist: bool,
+    icon_url: Option<String>,
+    // None means we don't care about the position
+    position: Option<u16>,
+    // role exists but isn't tracked yet
+    adopted: bool,
+}
+
+enum ChangeOperation {
+    Create {
+        member: Uuid,
+        name: String,
+        color: u32,
+        hoist: bool,
+        icon_url: Option<String>,
+        position: Option<u16>,
+    },
+    Delete {
+        id: serenity::RoleId,
+        name: String,
+    },
+    // start tracking a role that already looks right
+    Adopt {
+        member: Uuid,
+        id: serenity::RoleId,
+        name: String,
+    },
+    // stop tracking a role that got deleted outside of tulpje, so it gets recreated
+    Forget {
+        role_id: u64,
+    },
+    Update {
+        member: Uuid,
+        id: serenity::RoleId,
+        name: String,
+        color: u32,
+        hoist: bool,
+        icon_url: Option<String>,
+        // only set the icon when it changed, as it needs to be downloaded first
+        update_icon: bool,
+        position: Option<u16>,
+    },
+}
+
+// settings that decide what the alter roles look like
+struct RoleAttributes<'a> {
+    template: &'a str,
+    icons: bool,
+    hoist: bool,
+    order: RoleOrder,
+    order_group: Option<&'a str>,
+    // only members of this group get a role
+    member_group: Option<&'a str>,
+    private_members: PrivateMembers,
+    // lowest position of the block of alter roles
+    base_position: u16,
+}
+
+async fn get_desired_roles(
+    pk: &PkApi,
+    system_id: &str,
+    token: &str,
+    attrs: &RoleAttributes<'_>,
+) -> Result<HashMap<Uuid, MemberRole>, Error> {
+    let group_members = match attrs.member_group {
+        Some(group) => Some(get_group_member_ids(pk, system_id, group, token).await?),
+        None => None,
+    };
+
+    // NOTE: placeholder roles would all look the same and give away how many
+    //       private members there are, so private members don't get a role
+    let private_members = match attrs.private_members {
+        PrivateMembers::Placeholder => &PrivateMembers::Hide,
+        ref setting => setting,
+    };
+    let members: Vec<Member> = apply_privacy(
+        get_system_members(pk, system_id, token).await?,
+        private_members,
+    )
+    .into_iter()
+    .filter(|m| group_members.as_ref().is_none_or(|g| g.contains(&m.uuid)))
+    .filter(|m| match naming::render_role_name(attrs.template, m) {
+        Ok(_) => true,
+        Err(err) => {
+            warn!(member_id = m.id.0, "skipping member: {}", err);
+            false
+        }
+    })
+    .collect();
+
+    let group_order: Vec<Uuid> = match (&attrs.order, attrs.order_group) {
+        (RoleOrder::Group, Some(group)) => pk
+            .get_group_members(system_id, group, token)
+            .await?
+            .into_iter()
+            .map(|m| m.uuid)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    // highest position goes to the first member
+    let positions: HashMap<Uuid, u16> = order_members(&members, &attrs.order, &group_order)
+        .into_iter()
+        .rev()
+        .enumerate()
+        .map(|(idx, uuid)| (uuid, attrs.base_position + idx as u16))
+        .collect();
+
+    let roles = members
+        .into_iter()
+        .map(|m| {
+            (
+                m.uuid,
+                MemberRole {
+                    id: None,
+                    name: naming::render_role_name(attrs.template, &m)
+                        .expect("invalid names are filtered out above"),
+                    color: hex_to_color(m.color).0,
+                    hoist: attrs.hoist,
+                    icon_url: match attrs.icons {
+                        true => m.avatar_url.map(|url| url.to_string()),
+                        false => None,
+                    },
+                    position: match attrs.order {
+                        RoleOrder::None => None,
+                        _ => positions.get(&m.uuid).copied(),
+                    },
+                    adopted: false,
+                },
+            )
+        })
+        .collect();
+
+    Ok(roles)
+}
+
+// roles we created ourselves for this system, tracked by PluralKit member uuid,
+// also returns the ids of tracked roles that no longer exist
+async fn get_current_roles(
+    db: &sqlx::PgPool,
+    guild: &PartialGuild,
+    system_id: &str,
+) -> Result<(HashMap<Uuid, MemberRole>, Vec<u64>), Error> {
+    let mut roles = HashMap::new();
+    let mut deleted = Vec::new();
+
+    for row in roles_db::get_managed_roles(db, guild.id.get(), system_id).await? {
+        let Some(role) = guild.roles.get(&serenity::RoleId::new(row.role_id)) else {
+            debug!(
+                guild_id = guild.id.get(),
+                role_id = row.role_id,
+                "managed role no longer exists"
+            );
+            deleted.push(row.role_id);
+            continue;
+        };
+
+        roles.insert(
+            row.member_uuid,
+            MemberRole {
+                id: Some(role.id),
+                name: role.name.clone(),
+                color: role.colour.0,
+                hoist: role.hoist,
+                icon_url: row.icon_url,
+                position: Some(role.position),
+                adopted: false,
+            },
+        );
+    }
+
+    Ok((roles, deleted))
+}
+
+// roles created before we tracked them by id are recognised by the marker, and
+// adopted if their name is the desired name, or the desired name under an
+// earlier template with the same marker, anything else is left alone, including
+// roles tracked for other systems in the guild
+fn adopt_untracked_roles(
+    roles: &HashMap<serenity::RoleId, serenity::Role>,
+    marker: &str,
+    tracked: &HashSet<serenity::RoleId>,
+    current: &mut HashMap<Uuid, MemberRole>,
+    desired: &HashMap<Uuid, MemberRole>,
+) {
+    let mut adopted: HashSet<serenity::RoleId> = HashSet::new();
+
+    for (member, desired_role) in desired.iter() {
+        if current.contains_key(member) {
+            continue;
+        }
+
+        let untracked = || {
+            roles.values().filter(|r| {
+                r.name.contains(marker) && !tracked.contains(&r.id) && !adopted.contains(&r.id)
+            })
+        };
+        let unmarked = naming::strip_marker(&desired_role.name, marker);
+        let role = untracked()
+            .find(|r| r.name == desired_role.name)
+            .or_else(|| {
+                untracked().find(|r| {
+                    !unmarked.is_empty() && naming::strip_marker(&r.name, marker) == unmarked
+                })
+            })
+            .cloned();
+
+        if let Some(role) = role {
+            adopted.insert(role.id);
+            current.insert(
+                *member,
+                MemberRole {
+                    id: Some(role.id),
+                    name: role.name.clone(),
+                    color: role.colour.0,
+                    hoist: role.hoist,
+                    icon_url: None,
+                    position: Some(role.position),
+                    adopted: true,
+                },
+            );
+        }
+    }
+}
+
+// members of the given roles from the highest role down, only counting roles with
+// a position that `include` accepts
+fn role_order(roles: &HashMap<Uuid, MemberRole>, include: impl Fn(&Uuid) -> bool) -> Vec<Uuid> {
+    let mut ordered: Vec<(&Uuid, &MemberRole)> = roles
+        .iter()
+        .filter(|(member, role)| role.position.is_some() && include(member))
+        .collect();
+    // NOTE: discord allows roles to share a position, the id breaks those ties
+    ordered.sort_by_key(|(_, role)| (Reverse(role.position), role.id));
+    ordered.into_iter().map(|(member, _)| *member).collect()
+}
+
+// NOTE: absolute positions shift whenever other roles in the guild are added or
+//       moved, so only the order among the roles we manage is compared
+fn order_changed(current: &HashMap<Uuid, MemberRole>, desired: &HashMap<Uuid, MemberRole>) -> bool {
+    let current_order = role_order(current, |m| {
+        desired.get(m).is_some_and(|r| r.position.is_some())
+    });
+    let desired_order = role_order(desired, |m| current.contains_key(m));
+    current_order != desired_order
+}
+
+fn get_ops(
+    current: HashMap<Uuid, MemberRole>,
+    desired: HashMap<Uuid, MemberRole>,
+) -> Vec<ChangeOperation> {
+    // positions are only sent when the order changed, every role gets moved then
+    let reorder = order_changed(&current, &desired);
+    let all_members: HashSet<&Uuid> = HashSet::from_iter(current.keys().chain(desired.keys()));
+
+    all_members
+        .into_iter()
+        .filter_map(|member| {
+            match (current.get(member), desired.get(member)) {
+                // Update, only if anything we manage changed
+                (Some(current), Some(desired)) => {
+                    let update_icon = current.icon_url != desired.icon_url;
+                    let position = desired.position.filter(|_| reorder);
+
+                    if current.name != desired.name
+                        || current.color != desired.color
+                        || current.hoist != desired.hoist
+                        || update_icon
+                        || position.is_some()
+                    {
+                        Some(ChangeOperation::Update {
+                            member: *member,
+                            id: current.id.unwrap(),
+                            name: desired.name.clone(),
+                            color: desired.color,
+                            hoist: desired.hoist,
+                            icon_url: desired.icon_url.clone(),
+                            update_icon,
+                            position,
+                        })
+                    } else if current.adopted {
+                        Some(ChangeOperation::Adopt {
+                            member: *member,
+                            id: current.id.unwrap(),
+                            name: current.name.clone(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                // Create
+                (None, Some(desired)) => Some(ChangeOperation::Create {
+                    member: *member,
+                    name: desired.name.clone(),
+                    color: desired.color,
+                    hoist: desired.hoist,
+                    icon_url: desired.icon_url.clone(),
+                    position: desired.position,
+                }),
+                // Delete
+                (Some(current), None) => Some(ChangeOperation::Delete {
+                    id: current.id.unwrap(),
+                    name: current.name.clone(),
+                }),
+                // Shit got fucked up aaaa
+                (None, None) => panic!("current and desired are both None, shouldn't happen"),
+            }
+        })
+        .collect()
+}
+
+impl std::fmt::Display for ChangeOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Create { name, color, .. } => {
+                write!(f, "**Create** {} (#{:06X})", name, color)
+            }
+            Self::Update { name, color, .. } => write!(f, "**Update** {} (#{:06X})", name, color),
+            Self::Delete { name, .. } => write!(f, "**Delete** {}", name),
+            Self::Adopt { name, .. } => write!(f, "**Adopt** {}", name),
+            Self::Forget { role_id } => write!(f, "**Forget** deleted role {}", role_id),
+        }
+    }
+}
+
+fn create_ops_embed(
+    guild: &serenity::PartialGuild,
+    ops: &[ChangeOperation],
+) -> serenity::CreateEmbed {
+    let (created, deleted, updated) = count_ops(ops);
+
+    // NOTE: embed descriptions are limited to 4096 characters, so only list as
+    //       many operations as fit and summarise the rest
+    let mut description = String::new();
+    for (idx, op) in ops.iter().enumerate() {
+        let line = format!("{}\n", op);
+        if description.len() + line.len() > 4000 {
+            description.push_str(&format!("... and {} more", ops.len() - idx));
+            break;
+        }
+        description.push_str(&line);
+    }
+
+    serenity::CreateEmbed::new()
+        .title(format!("Planned role changes for {}", guild.name))
+        .description(description)
+        .footer(serenity::CreateEmbedFooter::new(format!(
+            "{} to create, {} to delete, {} to update",
+            created, deleted, updated
+        )))
+}
+
+// what a plan looks like to a moderator, regardless of order
+fn op_summary(ops: &[ChangeOperation]) -> Vec<String> {
+    let mut summary: Vec<String> = ops.iter().map(|op| op.to_string()).collect();
+    summary.sort();
+    summary
+}
+
+fn count_ops(ops: &[ChangeOperation]) -> (usize, usize, usize) {
+    ops.iter()
+        .fold((0, 0, 0), |(created, deleted, updated), op| match op {
+            ChangeOperation::Create { .. } => (created + 1, deleted, updated),
+            ChangeOperation::Delete { .. } => (created, deleted + 1, updated),
+            ChangeOperation::Update { .. } => (created, deleted, updated + 1),
+            // only touch the database
+            ChangeOperation::Adopt { .. } | ChangeOperation::Forget { .. } => {
+                (created, deleted, updated)
+            }
+        })
+}
+
+async fn get_role_ops(
+    data: &Data,
+    guild: &serenity::PartialGuild,
+    gs: &ModPkGuildRow,
+) -> Result<Vec<ChangeOperation>, Error> {
+    if !gs.verified {
+        return Err(UNVERIFIED_ERROR.into());
+    }
+
+    let (mut current_role_map, deleted_roles) =
+        get_current_roles(&data.db, guild, &gs.system_id).await?;
+    let tracked: HashSet<serenity::RoleId> =
+        roles_db::get_guild_managed_role_ids(&data.db, guild.id.get())
+            .await?
+            .into_iter()
+            .map(serenity::RoleId::new)
+            .collect();
+
+    let attrs = RoleAttributes {
+        template: &gs.role_name_template,
+        // role icons are only available for boosted guilds
+        icons: gs.role_icons && guild.features.iter().any(|f| f == "ROLE_ICONS"),
+        hoist: gs.role_hoist,
+        order: RoleOrder::try_from_string(&gs.role_order)?,
+        order_group: gs.role_order_group.as_deref(),
+        member_group: gs.member_group.as_deref(),
+        private_members: PrivateMembers::try_from_string(&gs.private_members)?,
+        base_position: current_role_map
+            .values()
+            .filter_map(|r| r.position)
+            .min()
+            .unwrap_or(1)
+            .max(1),
+    };
+
+    let desired_role_map = get_desired_roles(
+        &data.pk,
+        &gs.system_id,
+        &data.pk_tokens.decrypt_stored(&gs.token)?,
+        &attrs,
+    )
+    .await?;
+    adopt_untracked_roles(
+        &guild.roles,
+        &gs.role_marker,
+        &tracked,
+        &mut current_role_map,
+        &desired_role_map,
+    );
+
+    let mut ops = get_ops(current_role_map, desired_role_map);
+    ops.extend(
+        deleted_roles
+            .into_iter()
+            .map(|role_id| ChangeOperation::Forget { role_id }),
+    );
+    Ok(ops)
+}
+
+// compute and apply role changes without confirmation, deleting roles needs a
+// moderator to confirm them with /update-member-roles, as a renamed member or a
+// wrong system would otherwise wipe the roles, returns the amount of (created,
+// updated) roles and the names of roles that would've been deleted
+pub(crate) async fn sync_member_roles(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild: &serenity::PartialGuild,
+    gs: &ModPkGuildRow,
+) -> Result<(usize, usize, Vec<String>), Error> {
+    let (deletes, ops): (Vec<ChangeOperation>, Vec<ChangeOperation>) =
+        get_role_ops(data, guild, gs)
+            .await?
+            .into_iter()
+            .partition(|op| matches!(op, ChangeOperation::Delete { .. }));
+    apply_ops(ctx, &data.db, guild, &gs.system_id, &ops).await?;
+
+    let (created, _, updated) = count_ops(&ops);
+    let stale = deletes
+        .into_iter()
+        .filter_map(|op| match op {
+            ChangeOperation::Delete { name, .. } => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    Ok((created, updated, stale))
+}
+
+// download the icon and add it to the role, on failure the role is left without
+// an icon so a broken avatar doesn't block the rest of the sync
+async fn with_icon<'a>(
+    ctx: &serenity::Context,
+    builder: serenity::EditRole<'a>,
+    icon_url: &Option<String>,
+) -> (serenity::EditRole<'a>, Option<String>) {
+    let Some(url) = icon_url else {
+        return (builder.icon(None), None);
+    };
+
+    match serenity::CreateAttachment::url(&ctx.http, url).await {
+        Ok(icon) => (builder.icon(Some(&icon)), Some(url.clone())),
+        Err(err) => {
+            warn!("couldn't download role icon {}: {}", url, err);
+            (builder, None)
+        }
+    }
+}
+
+async fn apply_ops(
+    ctx: &serenity::Context,
+    db: &sqlx::PgPool,
+    guild: &serenity::PartialGuild,
+    system_id: &str,
+    ops: &[ChangeOperation],
+) -> Result<(), Error> {
+    // TODO: actually handle errors
+    // TODO: set mention permissions?
+    for op in ops.iter() {
+        match op {
+            ChangeOperation::Update {
+                member,
+                id,
+                name,
+                color,
+                hoist,
+                icon_url,
+                update_icon,
+                position,
+            } => {
+                let mut builder = serenity::EditRole::new()
+                    .name(name)
+                    .colour(*color)
+                    .hoist(*hoist);
+                if let Some(position) = position {
+                    builder = builder.position(*position);
+                }
+
+                let saved_icon_url = match update_icon {
+                    true => {
+                        let (icon_builder, saved_icon_url) =
+                            with_icon(ctx, builder, icon_url).await;
+                        builder = icon_builder;
+                        saved_icon_url
+                    }
+                    false => icon_url.clone(),
+                };
+
+                guild.edit_role(ctx, id, builder).await?;
+                roles_db::save_managed_role(
+                    db,
+                    guild.id.get(),
+                    system_id,
+                    *member,
+                    id.get(),
+                    saved_icon_url.as_deref(),
+                )
+                .await?;
+
+                debug!(
+                    guild_id = guild.id.get(),
+                    guild_name = guild.name,
+                    "updated role: {}",
+                    name,
+                )
+            }
+            ChangeOperation::Create {
+                member,
+                name,
+                color,
+                hoist,
+                icon_url,
+                position,
+            } => {
+                let mut builder = serenity::EditRole::new()
+                    .name(name)
+                    .colour(*color)
+                    .hoist(*hoist);
+                if let Some(position) = position {
+                    builder = builder.position(*position);
+                }
+                let (builder, saved_icon_url) = match icon_url {
+                    Some(_) => with_icon(ctx, builder, icon_url).await,
+                    None => (builder, None),
+                };
+
+                let role = guild.create_role(ctx, builder).await?;
+                roles_db::save_managed_role(
+                    db,
+                    guild.id.get(),
+                    system_id,
+                    *member,
+                    role.id.get(),
+                    saved_icon_url.as_deref(),
+                )
+                .await?;
+                debug!(
+                    guild_id = guild.id.get(),
+                    guild_name = guild.name,
+                    "created role: {}",
+                    name
+                )
+            }
+            ChangeOperation::Delete { id, name } => {
+                guild.delete_role(ctx, id).await?;
+                roles_db::delete_managed_role(db, id.get()).await?;
+                debug!(
+                    guild_id = guild.id.get(),
+                    guild_name = guild.name,
+                    "deleted_role: {}",
+                    name
+                )
+            }
+            ChangeOperation::Adopt { member, id, name } => {
+                roles_db::save_managed_role(db, guild.id.get(), system_id, *member, id.get(), None)
+                    .await?;
+                debug!(
+                    guild_id = guild.id.get(),
+                    guild_name = guild.name,
+                    "adopted role: {}",
+                    name
+                )
+            }
+            ChangeOperation::Forget { role_id } => {
+                roles_db::delete_managed_role(db, *role_id).await?;
+                debug!(
+                    guild_id = guild.id.get(),
+                    guild_name = guild.name,
+                    role_id,
+                    "forgot deleted role"
+                )
+            }
+        };
+    }
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "update-member-roles",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn update_member_roles(
+    ctx: Context<'_>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?; // delay responding and make reply ephemeral
+
+    let guild = ctx.partial_guild().await.unwrap();
+    let gs = get_command_system(ctx, system).await?;
+
+    if !gs.verified {
+        ctx.reply(format!("error: {}", UNVERIFIED_ERROR)).await?;
+        return Ok(());
+    }
+
+    let ops = get_role_ops(ctx.data(), &guild, &gs).await?;
+
+    if ops.is_empty() {
+        ctx.reply("roles are already up to date").await?;
+        return Ok(());
+    }
+
+    // show the planned changes and wait for confirmation before touching any roles
+    let Some(mci) = confirm_changes(
+        ctx,
+        poise::CreateReply::default().embed(create_ops_embed(&guild, &ops)),
+        "role update",
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    mci.create_response(
+        ctx,
+        serenity::CreateInteractionResponse::UpdateMessage(
+            serenity::CreateInteractionResponseMessage::new()
+                .content("applying role changes...")
+                .components(vec![]),
+        ),
+    )
+    .await?;
+
+    // the background sync might've changed roles while waiting for confirmation,
+    // only apply what was shown
+    let _lock = ctx.data().lock_system(guild.id.get(), &gs.system_id).await;
+    let guild = ctx.http().get_guild(guild.id).await?;
+    if op_summary(&get_role_ops(ctx.data(), &guild, &gs).await?) != op_summary(&ops) {
+        mci.edit_response(
+            ctx,
+            serenity::EditInteractionResponse::new()
+                .content("roles changed in the meantime, nothing was applied, please run /update-member-roles again")
+                .embeds(vec![]),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    apply_ops(
+        ctx.serenity_context(),
+        &ctx.data().db,
+        &guild,
+        &gs.system_id,
+        &ops,
+    )
+    .await?;
+
+    // aggregate stats
+    let (created, deleted, updated) = count_ops(&ops);
+
+    mci.edit_response(
+        ctx,
+        serenity::EditInteractionResponse::new().content(format!(
+            "roles updated, {} created, {} deleted, {} updated",
+            created, deleted, updated
+        )),
+    )
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn update_fronter_roles(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild: serenity::PartialGuild,
+    gs: &ModPkGuildRow,
+) -> Result<(), Error> {
+    if !gs.verified {
+        return Err(UNVERIFIED_ERROR.into());
+    }
+
+    let user_id = serenity::UserId::new(u64::try_from(gs.user_id)?);
+    let member = guild.member(ctx, user_id).await?;
+
+    let fronters: HashSet<Uuid> = get_fronter_members(
+        &data.pk,
+        &gs.system_id,
+        &data.pk_tokens.decrypt_stored(&gs.token)?,
+        gs.member_group.as_deref(),
+        &PrivateMembers::try_from_string(&gs.private_members)?,
+    )
+    .await?
+    .members
+    .iter()
+    .map(|m| m.uuid)
+    .collect();
+
+    // only ever touch alter roles, any other roles the user has are left alone
+    for (uuid, role) in get_current_roles(&data.db, &guild, &gs.system_id).await?.0 {
+        let id = role.id.expect("current roles always have an id");
+        let should_have = fronters.contains(&uuid);
+        let has = member.roles.contains(&id);
+
+        if should_have && !has {
+            member.add_role(ctx, id).await?;
+            debug!(
+                guild_id = guild.id.get(),
+                user_id = user_id.get(),
+                "added fronter role: {}",
+                role.name
+            );
+        } else if !should_have && has {
+            member.remove_role(ctx, id).await?;
+            debug!(
+                guild_id = guild.id.get(),
+                user_id = user_id.get(),
+                "removed fronter role: {}",
+                role.name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "assign-fronter-roles",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn assign_fronter_roles(
+    ctx: Context<'_>,
+    #[description = "assign the alter roles of current fronters to the system owner"] enabled: bool,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let gs = get_command_system(ctx, system).await?;
+
+    if enabled && !gs.verified {
+        ctx.reply(format!("error: {}", UNVERIFIED_ERROR)).await?;
+        return Ok(());
+    }
+
+    db::save_assign_fronter_roles(&ctx.data().db, guild_id.get(), &gs.system_id, enabled).await?;
+
+    ctx.reply(match enabled {
+        true => format!(
+            "alter roles of current fronters will be assigned to <@{}>",
+            gs.user_id
+        ),
+        false => "fronter roles will no longer be assigned".to_string(),
+    })
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-role-sync",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_role_sync(
+    ctx: Context<'_>,
+    #[description = "periodically sync alter roles in the background"] enabled: bool,
+    #[description = "(optional) channel to report sync failures in"]
+    #[channel_types("Text")]
+    admin_channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let db = &ctx.data().db;
+
+    if db::get_guild_systems(db, guild_id.get()).await?.is_empty() {
+        return Err("PluralKit module not set-up, please run /setup-pk".into());
+    }
+
+    if !enabled {
+        roles_db::delete_role_sync(db, guild_id.get()).await?;
+        ctx.reply("alter roles will no longer be synced in the background")
+            .await?;
+        return Ok(());
+    }
+
+    roles_db::save_role_sync(
+        db,
+        guild_id.get(),
+        admin_channel.as_ref().map(|c| c.id.get()),
+    )
+    .await?;
+
+    ctx.reply(match admin_channel {
+        Some(channel) => format!(
+            "alter roles will be synced in the background, failures will be reported in <#{}>",
+            channel.id
+        ),
+        None => "alter roles will be synced in the background".to_string(),
+    })
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-role-names",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_role_names(
+    ctx: Context<'_>,
+    #[description = "role name template, e.g. `{name} (Alter)`, placeholders: {name}, {display_name}, {pronouns}, {id}"]
+    template: String,
+    #[description = "(optional) fixed text used to recognise alter roles, defaults to the template's fixed text"]
+    marker: Option<String>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let gs = get_command_system(ctx, system).await?;
+
+    // keep the current marker if it still fits the template, so renaming the
+    // template doesn't orphan the roles we created before
+    let marker = match marker {
+        Some(marker) => marker,
+        None if template.contains(&gs.role_marker) => gs.role_marker.clone(),
+        None => match naming::default_marker(&template) {
+            Some(marker) => marker,
+            None => {
+                ctx.reply("error: template has no fixed text to recognise alter roles by, please specify a marker")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    if let Err(err) = naming::validate_template(&template, &marker) {
+        ctx.reply(format!("error: {}", err)).await?;
+        return Ok(());
+    }
+
+    // make sure the template works for all current members
+    let token = ctx.data().pk_tokens.decrypt_stored(&gs.token)?;
+    let too_long: Vec<String> = get_system_members(&ctx.data().pk, &gs.system_id, &token)
+        .await?
+        .iter()
+        .filter_map(|m| naming::render_role_name(&template, m).err())
+        .collect();
+
+    if !too_long.is_empty() {
+        ctx.reply(format!(
+            "error: template doesn't work for all members:\n{}",
+            too_long.join("\n")
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    db::save_role_name_template(
+        &ctx.data().db,
+        guild_id.get(),
+        &gs.system_id,
+        &template,
+        &marker,
+    )
+    .await?;
+
+    ctx.reply(format!(
+        "alter roles will be named `{}`, existing roles containing `{}` with a matching name will be adopted, run /update-member-roles to apply",
+        template, marker
+    ))
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-role-attributes",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_role_attributes(
+    ctx: Context<'_>,
+    #[description = "use member avatars as role icons (needs boost level 2)"] icons: Option<bool>,
+    #[description = "display alter roles separately in the member list"] hoist: Option<bool>,
+    #[description = "how to order the alter roles"] order: Option<RoleOrder>,
+    #[description = "PluralKit group id to take the order from"] order_group: Option<String>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let gs = get_command_system(ctx, system).await?;
+
+    // unspecified options keep their current value
+    let icons = icons.unwrap_or(gs.role_icons);
+    let hoist = hoist.unwrap_or(gs.role_hoist);
+    let order = match order {
+        Some(order) => order,
+        None => RoleOrder::try_from_string(&gs.role_order)?,
+    };
+    let order_group = match order_group {
+        Some(group) => Some(group.trim().replace("-", "").to_lowercase()),
+        None => gs.role_order_group,
+    };
+
+    if order == RoleOrder::Group && order_group.is_none() {
+        ctx.reply("error: ordering by group requires a group id")
+            .await?;
+        return Ok(());
+    }
+
+    db::save_role_attributes(
+        &ctx.data().db,
+        guild_id.get(),
+        &gs.system_id,
+        icons,
+        hoist,
+        order.id(),
+        order_group.clone(),
+    )
+    .await?;
+
+    ctx.reply(format!(
+        "alter role attributes updated, icons: {}, hoist: {}, order: {}{}, run /update-member-roles to apply",
+        icons,
+        hoist,
+        order.name(),
+        match (&order, order_group) {
+            (RoleOrder::Group, Some(group)) => format!(" (`{}`)", group),
+            _ => "".into(),
+        },
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(id: Option<u64>, name: &str, adopted: bool) -> MemberRole {
+        MemberRole {
+            id: id.map(serenity::RoleId::new),
+            name: name.into(),
+            color: 0,
+            hoist: false,
+            icon_url: None,
+            position: None,
+            adopted,
+        }
+    }
+
+    #[test]
+    fn get_ops_adopt_test() {
+        let member = Uuid::from_u128(1);
+
+        // adopting a role that already looks right only tracks it
+        let ops = get_ops(
+            HashMap::from([(member, role(Some(1), "A (Alter)", true))]),
+            HashMap::from([(member, role(None, "A (Alter)", false))]),
+        );
+        assert!(matches!(ops[..], [ChangeOperation::Adopt { .. }]));
+
+        let ops = get_ops(
+            HashMap::from([(member, role(Some(1), "A (Alter)", false))]),
+            HashMap::from([(member, role(None, "A (Alter)", false))]),
+        );
+        assert!(ops.is_empty());
+
+        let ops = get_ops(
+            HashMap::from([(member, role(Some(1), "A (Alter)", true))]),
+            HashMap::from([(member, role(None, "B (Alter)", false))]),
+        );
+        assert!(matches!(ops[..], [ChangeOperation::Update { .. }]));
+    }
+
+    #[test]
+    fn adopt_untracked_roles_test() {
+        let guild_role = |id: u64, name: &str| {
+            let role: serenity::Role = serde_json::from_value(serde_json::json!({
+                "id": id.to_string(),
+                "guild_id": "1",
+                "name": name,
+                "color": 0,
+                "hoist": false,
+                "position": 1,
+                "permissions": "0",
+                "managed": false,
+                "mentionable": false,
+            }))
+            .unwrap();
+            (role.id, role)
+        };
+        let roles = HashMap::from([
+            guild_role(10, "Foo (Alter)"),
+            guild_role(11, "Bar (Alter)"),
+            guild_role(12, "Baz"),
+            guild_role(13, "Qux (Alter)"),
+        ]);
+        let (foo, bar, baz, qux) = (
+            Uuid::from_u128(1),
+            Uuid::from_u128(2),
+            Uuid::from_u128(3),
+            Uuid::from_u128(4),
+        );
+        let desired = HashMap::from([
+            (foo, role(None, "Foo (Alter)", false)),
+            // named under an earlier `{name} (Alter)` template
+            (bar, role(None, "(Alter) Bar", false)),
+            // no marker, so not one of ours
+            (baz, role(None, "(Alter) Baz", false)),
+            // tracked for another system
+            (qux, role(None, "Qux (Alter)", false)),
+        ]);
+
+        let mut current = HashMap::new();
+        adopt_untracked_roles(
+            &roles,
+            "(Alter)",
+            &HashSet::from([serenity::RoleId::new(13)]),
+            &mut current,
+            &desired,
+        );
+
+        let mut adopted: Vec<(Uuid, u64)> = current
+            .iter()
+            .map(|(member, r)| (*member, r.id.unwrap().get()))
+            .collect();
+        adopted.sort();
+        assert_eq!(adopted, vec![(foo, 10), (bar, 11)]);
+        assert!(current.values().all(|r| r.adopted));
+    }
+
+    #[test]
+    fn get_ops_position_test() {
+        let (a, b) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let positioned = |id: Option<u64>, name: &str, position: u16| MemberRole {
+            position: Some(position),
+            ..role(id, name, false)
+        };
+        let desired = || {
+            HashMap::from([
+                (a, positioned(None, "A (Alter)", 2)),
+                (b, positioned(None, "B (Alter)", 1)),
+            ])
+        };
+
+        // other roles moved the block, the order is still the same
+        let ops = get_ops(
+            HashMap::from([
+                (a, positioned(Some(1), "A (Alter)", 12)),
+                (b, positioned(Some(2), "B (Alter)", 10)),
+            ]),
+            desired(),
+        );
+        assert!(ops.is_empty());
+
+        // only send positions when the order changed
+        let ops = get_ops(
+            HashMap::from([
+                (a, positioned(Some(1), "A (Alter)", 10)),
+                (b, positioned(Some(2), "B2 (Alter)", 12)),
+            ]),
+            desired(),
+        );
+        assert_eq!(ops.len(), 2);
+        assert!(ops.iter().all(|op| matches!(
+            op,
+            ChangeOperation::Update {
+                position: Some(_),
+                ..
+            }
+        )));
+
+        let ops = get_ops(
+            HashMap::from([
+                (a, positioned(Some(1), "A (Alter)", 12)),
+                (b, positioned(Some(2), "B2 (Alter)", 10)),
+            ]),
+            desired(),
+        );
+        assert!(matches!(
+            ops[..],
+            [ChangeOperation::Update { position: None, .. }]
+        ));
+
+        // new roles still get a position
+        let ops = get_ops(
+            HashMap::from([(a, positioned(Some(1), "A (Alter)", 12))]),
+            desired(),
+        );
+        assert!(matches!(
+            ops[..],
+            [ChangeOperation::Create {
+                position: Some(1),
+                ..
+            }]
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+use sqlx::types::Uuid;
+
+use crate::types::Error;
+
+pub(crate) struct ModPkRoleSyncRow {
+    pub(crate) guild_id: u64,
+    pub(crate) admin_channel_id: Option<u64>,
+}
+
+pub(crate) async fn get_role_sync_settings(
+    db: &sqlx::PgPool,
+) -> Result<Vec<ModPkRoleSyncRow>, Error> {
+    let result = sqlx::query!("SELECT guild_id, admin_channel_id FROM mod_pk_role_sync")
+        .fetch_all(db)
+        .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|row| ModPkRoleSyncRow {
+            guild_id: row.guild_id.try_into().unwrap(),
+            admin_channel_id: row.admin_channel_id.map(|id| id.try_into().unwrap()),
+        })
+        .collect())
+}
+
+pub(crate) async fn get_role_sync_for_id(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+) -> Result<Option<ModPkRoleSyncRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT guild_id, admin_channel_id FROM mod_pk_role_sync WHERE guild_id = $1",
+        i64::try_from(guild_id)?,
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(result.map(|row| ModPkRoleSyncRow {
+        guild_id: row.guild_id.try_into().unwrap(),
+        admin_channel_id: row.admin_channel_id.map(|id| id.try_into().unwrap()),
+    }))
+}
+
+pub(crate) async fn save_role_sync(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    admin_channel_id: Option<u64>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO mod_pk_role_sync (guild_id, admin_channel_id) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET admin_channel_id = $2",
+        i64::try_from(guild_id)?,
+        admin_channel_id.map(i64::try_from).transpose()?,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_role_sync(db: &sqlx::PgPool, guild_id: u64) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_role_sync WHERE guild_id = $1",
+        i64::try_from(guild_id)?,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) struct ModPkRolesRow {
+    pub(crate) member_uuid: Uuid,
+    pub(crate) role_id: u64,
+    pub(crate) icon_url: Option<String>,
+}
+
+pub(crate) async fn get_managed_roles(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<Vec<ModPkRolesRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT member_uuid, role_id, icon_url FROM mod_pk_roles WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|row| ModPkRolesRow {
+            member_uuid: row.member_uuid,
+            role_id: row.role_id.try_into().unwrap(),
+            icon_url: row.icon_url,
+        })
+        .collect())
+}
+
+// role ids managed for any system in the guild
+pub(crate) async fn get_guild_managed_role_ids(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+) -> Result<Vec<u64>, Error> {
+    let result = sqlx::query_scalar!(
+        "SELECT role_id FROM mod_pk_roles WHERE guild_id = $1",
+        i64::try_from(guild_id)?,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|role_id| role_id.try_into().unwrap())
+        .collect())
+}
+
+pub(crate) async fn save_managed_role(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    member_uuid: Uuid,
+    role_id: u64,
+    icon_url: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO mod_pk_roles (guild_id, system_id, member_uuid, role_id, icon_url) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (guild_id, member_uuid) DO UPDATE SET system_id = $2, role_id = $4, icon_url = $5",
+        i64::try_from(guild_id)?,
+        system_id,
+        member_uuid,
+        i64::try_from(role_id)?,
+        icon_url,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_managed_role(db: &sqlx::PgPool, role_id: u64) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_roles WHERE role_id = $1",
+        i64::try_from(role_id)?,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_managed_roles(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_roles WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use pkrs::model::Member;
+
+use crate::modules::pk::template::{parse_template, strip_pronouns, Part};
+use crate::util::get_member_name;
+
+// discord doesn't allow role names longer than this
+pub(crate) const MAX_ROLE_NAME_LENGTH: usize = 100;
+
+const PLACEHOLDERS: [&str; 4] = ["name", "display_name", "pronouns", "id"];
+
+pub(crate) fn render_role_name(template: &str, member: &Member) -> Result<String, String> {
+    let display_name = get_member_name(member);
+
+    let name = parse_template(template, &PLACEHOLDERS)?
+        .into_iter()
+        .map(|part| match part {
+            Part::Literal(text) => text,
+            Part::Placeholder("name") => strip_pronouns(&display_name),
+            Part::Placeholder("display_name") => &display_name,
+            Part::Placeholder("pronouns") => member.pronouns.as_deref().unwrap_or(""),
+            Part::Placeholder("id") => &member.id.0,
+            Part::Placeholder(_) => unreachable!("placeholders are validated when parsing"),
+        })
+        .collect::<String>();
+
+    if name.chars().count() > MAX_ROLE_NAME_LENGTH {
+        return Err(format!(
+            "role name `{}` is longer than {} characters",
+            name, MAX_ROLE_NAME_LENGTH
+        ));
+    }
+
+    Ok(name)
+}
+
+// the longest bit of fixed text in the template, used as a marker when none is specified
+pub(crate) fn default_marker(template: &str) -> Option<String> {
+    parse_template(template, &PLACEHOLDERS)
+        .ok()?
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Literal(text) if !text.trim().is_empty() => Some(text),
+            _ => None,
+        })
+        .max_by_key(|text| text.chars().count())
+        .map(|text| text.to_owned())
+}
+
+// the name without the marker, so names under different templates with the same
+// marker can be compared, e.g. `Foo (Alter)` and `(Alter) Foo`
+pub(crate) fn strip_marker(name: &str, marker: &str) -> String {
+    name.replacen(marker, " ", 1)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+pub(crate) fn validate_template(template: &str, marker: &str) -> Result<(), String> {
+    let parts = parse_template(template, &PLACEHOLDERS)?;
+
+    if !parts.iter().any(|p| matches!(p, Part::Placeholder(_))) {
+        return Err("template needs at least one placeholder".into());
+    }
+
+    if marker.trim().is_empty() {
+        return Err("marker can't be empty".into());
+    }
+
+    // the marker needs to be fixed text, otherwise we can't recognise our roles
+    if !parts
+        .iter()
+        .any(|p| matches!(p, Part::Literal(text) if text.contains(marker)))
+    {
+        return Err(format!(
+            "marker `{}` needs to be part of the fixed text in the template",
+            marker
+        ));
+    }
+
+    let literal_len: usize = parts
+        .iter()
+        .map(|p| match p {
+            Part::Literal(text) => text.chars().count(),
+            Part::Placeholder(_) => 0,
+        })
+        .sum();
+    if literal_len >= MAX_ROLE_NAME_LENGTH {
+        return Err(format!(
+            "template leaves no room for names within the {} character limit",
+            MAX_ROLE_NAME_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    fn member(name: &str, display_name: Option<&str>, pronouns: Option<&str>) -> Member {
+        test_member(serde_json::json!({
+            "name": name,
+            "display_name": display_name,
+            "pronouns": pronouns,
+        }))
+    }
+
+    #[test]
+    fn render_role_name_test() {
+        let m = member("foo", Some("Foo (she/her)"), Some("she/her"));
+        assert_eq!(
+            render_role_name("{name} (Alter)", &m).unwrap(),
+            "Foo (Alter)"
+        );
+        assert_eq!(
+            render_role_name("alter: {display_name}", &m).unwrap(),
+            "alter: Foo (she/her)"
+        );
+        assert_eq!(
+            render_role_name("{name} ✦ {pronouns} [{id}]", &m).unwrap(),
+            "Foo ✦ she/her [abcde]"
+        );
+
+        assert!(render_role_name("{nope}", &m).is_err());
+        assert!(render_role_name(&format!("{}{{name}}", "a".repeat(98)), &m).is_err());
+    }
+
+    #[test]
+    fn default_marker_test() {
+        assert_eq!(default_marker("{name} (Alter)"), Some(" (Alter)".into()));
+        assert_eq!(
+            default_marker("alter: {display_name}"),
+            Some("alter: ".into())
+        );
+        assert_eq!(default_marker("{name}"), None);
+    }
+
+    #[test]
+    fn strip_marker_test() {
+        assert_eq!(strip_marker("Foo (Alter)", "(Alter)"), "Foo");
+        assert_eq!(strip_marker("(Alter) Foo", "(Alter)"), "Foo");
+        assert_eq!(strip_marker("Foo ✦ she/her", "✦"), "Foo she/her");
+        assert_eq!(strip_marker("Foo", "(Alter)"), "Foo");
+    }
+
+    #[test]
+    fn validate_template_test() {
+        assert!(validate_template("{name} (Alter)", " (Alter)").is_ok());
+        assert!(validate_template("{name} ✦", "✦").is_ok());
+        assert!(validate_template("no placeholder", "no").is_err());
+        assert!(validate_template("{name} ✦", "").is_err());
+        assert!(validate_template("{name} ✦", "{name}").is_err());
+        assert!(validate_template(&format!("{{name}}{}", "a".repeat(100)), "a").is_err());
+    }
+}
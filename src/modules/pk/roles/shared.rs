@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use pkrs::model::Member;
+use sqlx::types::Uuid;
+
+use crate::types::Error;
+use crate::util::get_member_name;
+
+#[derive(Debug, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum RoleOrder {
+    #[name = "Don't Reorder"]
+    None,
+    #[name = "Alphabetical"]
+    Alphabetical,
+    #[name = "Member Creation Date"]
+    Created,
+    #[name = "PluralKit Group Order"]
+    Group,
+}
+
+impl RoleOrder {
+    // alias poise::ChoiceParameter::name to avoid extra imports
+    pub(crate) fn name(&self) -> &'static str {
+        poise::ChoiceParameter::name(self)
+    }
+
+    pub(crate) fn id(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Alphabetical => "alphabetical",
+            Self::Created => "created",
+            Self::Group => "group",
+        }
+    }
+
+    pub(crate) fn try_from_string(string: &str) -> Result<Self, Error> {
+        match string {
+            "none" => Ok(Self::None),
+            "alphabetical" => Ok(Self::Alphabetical),
+            "created" => Ok(Self::Created),
+            "group" => Ok(Self::Group),
+            _ => Err(format!("unknown role order {}", string).into()),
+        }
+    }
+}
+
+// order members top to bottom, members missing from `group_order` go below
+// the ones in it, sorted alphabetically
+pub(crate) fn order_members(
+    members: &[Member],
+    order: &RoleOrder,
+    group_order: &[Uuid],
+) -> Vec<Uuid> {
+    let mut members: Vec<&Member> = members.iter().collect();
+    let by_name = |a: &&Member, b: &&Member| {
+        get_member_name(a)
+            .to_lowercase()
+            .cmp(&get_member_name(b).to_lowercase())
+    };
+
+    match order {
+        RoleOrder::None | RoleOrder::Alphabetical => members.sort_by(by_name),
+        RoleOrder::Created => members.sort_by(|a, b| a.created.cmp(&b.created).then(by_name(a, b))),
+        RoleOrder::Group => {
+            let group_pos: HashMap<&Uuid, usize> = group_order
+                .iter()
+                .enumerate()
+                .map(|(idx, uuid)| (uuid, idx))
+                .collect();
+
+            members.sort_by(|a, b| {
+                let a_pos = group_pos.get(&a.uuid).unwrap_or(&usize::MAX);
+                let b_pos = group_pos.get(&b.uuid).unwrap_or(&usize::MAX);
+                a_pos.cmp(b_pos).then(by_name(a, b))
+            })
+        }
+    }
+
+    members.into_iter().map(|m| m.uuid).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    fn member(uuid: u128, name: &str, created: &str) -> Member {
+        test_member(serde_json::json!({
+            "uuid": Uuid::from_u128(uuid),
+            "name": name,
+            "created": created,
+        }))
+    }
+
+    #[test]
+    fn order_members_test() {
+        let members = vec![
+            member(1, "charlie", "2020-01-01T00:00:00Z"),
+            member(2, "Alice", "2022-01-01T00:00:00Z"),
+            member(3, "bob", "2021-01-01T00:00:00Z"),
+        ];
+        let uuids =
+            |ids: &[u128]| -> Vec<Uuid> { ids.iter().map(|id| Uuid::from_u128(*id)).collect() };
+
+        assert_eq!(
+            order_members(&members, &RoleOrder::Alphabetical, &[]),
+            uuids(&[2, 3, 1])
+        );
+        assert_eq!(
+            order_members(&members, &RoleOrder::Created, &[]),
+            uuids(&[1, 3, 2])
+        );
+        assert_eq!(
+            order_members(&members, &RoleOrder::Group, &uuids(&[3])),
+            uuids(&[3, 2, 1])
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude::{self as serenity};
+use tracing::{error, info, warn};
+
+use crate::modules::pk;
+use crate::modules::pk::shared::receives_dispatch;
+use crate::types::{Data, Error};
+
+use self::pk::db::ModPkGuildRow;
+
+use super::db::ModPkRoleSyncRow;
+
+pub(crate) async fn update_fronter_roles(
+    ctx: &serenity::Context,
+    data: Arc<Data>,
+) -> Result<(), Error> {
+    update_fronter_roles_where(ctx, &data, |gs| !receives_dispatch(&data, gs)).await
+}
+
+// fallback for systems that receive dispatch webhooks, in case one got lost
+pub(crate) async fn update_dispatch_fronter_roles(
+    ctx: &serenity::Context,
+    data: Arc<Data>,
+) -> Result<(), Error> {
+    update_fronter_roles_where(ctx, &data, |gs| receives_dispatch(&data, gs)).await
+}
+
+async fn update_fronter_roles_where(
+    ctx: &serenity::Context,
+    data: &Data,
+    filter: impl Fn(&ModPkGuildRow) -> bool,
+) -> Result<(), Error> {
+    let guild_settings = pk::db::get_guild_settings(&data.db).await?;
+
+    for gs in guild_settings
+        .iter()
+        .filter(|gs| gs.assign_fronter_roles && gs.verified && filter(gs))
+    {
+        let _lock = data
+            .lock_system(u64::try_from(gs.guild_id)?, &gs.system_id)
+            .await;
+        if let Err(err) = update_fronter_roles_for_guild(ctx, data, gs).await {
+            error!(
+                guild_id = gs.guild_id,
+                system_id = gs.system_id,
+                user_id = gs.user_id,
+                err
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn update_fronter_roles_for_guild(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+) -> Result<(), Error> {
+    let guild = ctx
+        .http
+        .get_guild(serenity::GuildId::new(u64::try_from(gs.guild_id)?))
+        .await?;
+
+    super::commands::update_fronter_roles(ctx, data, guild.clone(), gs)
+        .await
+        .map_err(|err| {
+            format!(
+                "error updating fronter roles for {} ({}) system {}: {}",
+                guild.name, guild.id, gs.system_id, err
+            )
+        })?;
+
+    info!(
+        guild.id = guild.id.get(),
+        guild.name = guild.name,
+        system_id = gs.system_id,
+        "fronter roles updated"
+    );
+
+    Ok(())
+}
+
+pub(crate) async fn sync_member_roles(
+    ctx: &serenity::Context,
+    data: Arc<Data>,
+) -> Result<(), Error> {
+    let sync_settings = super::db::get_role_sync_settings(&data.db).await?;
+    let guild_settings = pk::db::get_guild_settings(&data.db).await?;
+
+    for sync in sync_settings {
+        // role sync is enabled per guild, and covers all of its systems
+        let cur_guild_settings: Vec<&ModPkGuildRow> = guild_settings
+            .iter()
+            .filter(|gs| u64::try_from(gs.guild_id).unwrap() == sync.guild_id)
+            .collect();
+
+        if cur_guild_settings.is_empty() {
+            warn!(
+                guild_id = sync.guild_id,
+                "couldn't find guild settings for guild"
+            );
+            continue;
+        }
+
+        // unverified systems can't have alter roles
+        for gs in cur_guild_settings.into_iter().filter(|gs| gs.verified) {
+            let _lock = data.lock_system(sync.guild_id, &gs.system_id).await;
+            if let Err(err) = sync_member_roles_for_guild(ctx, &data, gs, &sync).await {
+                error!(guild_id = sync.guild_id, system_id = gs.system_id, err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// sync roles and tell the admin channel about errors, and roles that'd need to
+// be deleted, those are never deleted without confirmation
+pub(crate) async fn sync_member_roles_for_guild(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+    sync: &ModPkRoleSyncRow,
+) -> Result<(), Error> {
+    let result = sync_roles(ctx, data, gs).await;
+
+    let report = match &result {
+        Ok(stale) if stale.is_empty() => None,
+        Ok(stale) => Some(format!(
+            "**{} alter role(s) no longer match a member** of system `{}` and were left alone, run /update-member-roles to review and delete them: {}",
+            stale.len(),
+            gs.system_id,
+            format_role_names(stale)
+        )),
+        Err(err) => Some(format!("**Error** syncing alter roles: {}", err)),
+    };
+    report_sync(ctx, data, sync, &gs.system_id, report).await;
+
+    result.map(|_| ())
+}
+
+// returns the names of roles that would've been deleted
+async fn sync_roles(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+) -> Result<Vec<String>, Error> {
+    let guild = ctx
+        .http
+        .get_guild(serenity::GuildId::new(u64::try_from(gs.guild_id)?))
+        .await?;
+
+    let (created, updated, stale) = super::commands::sync_member_roles(ctx, data, &guild, gs)
+        .await
+        .map_err(|err| {
+            format!(
+                "error syncing member roles for {} ({}) system {}: {}",
+                guild.name, guild.id, gs.system_id, err
+            )
+        })?;
+
+    info!(
+        guild.id = guild.id.get(),
+        guild.name = guild.name,
+        system_id = gs.system_id,
+        created,
+        updated,
+        stale = stale.len(),
+        "member roles synced"
+    );
+
+    Ok(stale)
+}
+
+// keeps reports within discord's message length limit
+fn format_role_names(names: &[String]) -> String {
+    const MAX_NAMES: usize = 20;
+
+    let mut text = names
+        .iter()
+        .take(MAX_NAMES)
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<String>>()
+        .join(", ");
+    if names.len() > MAX_NAMES {
+        text += &format!(" and {} more", names.len() - MAX_NAMES);
+    }
+    text
+}
+
+// last thing reported to the admin channel, so a problem that persists isn't
+// posted on every sync
+#[derive(Debug, Clone)]
+pub(crate) struct SyncReport {
+    message: String,
+    reported_at: Instant,
+    // how many times in a row this message was reported
+    times: u32,
+}
+
+// the same report is repeated after this, doubling every time
+const REPORT_BACKOFF: Duration = Duration::from_secs(60 * 60);
+const MAX_REPORT_BACKOFF: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn report_backoff(times: u32) -> Duration {
+    REPORT_BACKOFF
+        .saturating_mul(2u32.saturating_pow(times.saturating_sub(1)))
+        .min(MAX_REPORT_BACKOFF)
+}
+
+fn report_due(previous: Option<&SyncReport>, message: &str, now: Instant) -> bool {
+    match previous {
+        Some(previous) if previous.message == message => {
+            now >= previous.reported_at + report_backoff(previous.times)
+        }
+        _ => true,
+    }
+}
+
+// None means everything is fine again, so the next problem is reported right away
+async fn report_sync(
+    ctx: &serenity::Context,
+    data: &Data,
+    sync: &ModPkRoleSyncRow,
+    system_id: &str,
+    message: Option<String>,
+) {
+    let key = (sync.guild_id, system_id.to_owned());
+    let Some(message) = message else {
+        data.role_sync_reports.remove(&key);
+        return;
+    };
+
+    let now = Instant::now();
+    let previous = data.role_sync_reports.get(&key).map(|r| r.clone());
+    if !report_due(previous.as_ref(), &message, now) {
+        return;
+    }
+
+    let times = match previous {
+        Some(previous) if previous.message == message => previous.times + 1,
+        _ => 1,
+    };
+    data.role_sync_reports.insert(
+        key,
+        SyncReport {
+            message: message.clone(),
+            reported_at: now,
+            times,
+        },
+    );
+
+    let Some(channel_id) = sync.admin_channel_id else {
+        return;
+    };
+
+    if let Err(send_err) = serenity::ChannelId::new(channel_id).say(ctx, message).await {
+        error!(
+            guild_id = sync.guild_id,
+            channel_id, "couldn't report role sync: {}", send_err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_due_test() {
+        let now = Instant::now();
+        let previous = SyncReport {
+            message: "error".into(),
+            reported_at: now,
+            times: 1,
+        };
+
+        assert!(report_due(None, "error", now));
+        assert!(report_due(Some(&previous), "other error", now));
+        assert!(!report_due(Some(&previous), "error", now));
+        assert!(report_due(Some(&previous), "error", now + REPORT_BACKOFF));
+
+        let previous = SyncReport {
+            times: 3,
+            ..previous
+        };
+        assert!(!report_due(
+            Some(&previous),
+            "error",
+            now + REPORT_BACKOFF * 3
+        ));
+        assert!(report_due(
+            Some(&previous),
+            "error",
+            now + REPORT_BACKOFF * 4
+        ));
+    }
+
+    #[test]
+    fn report_backoff_test() {
+        assert_eq!(report_backoff(1), REPORT_BACKOFF);
+        assert_eq!(report_backoff(2), REPORT_BACKOFF * 2);
+        assert_eq!(report_backoff(10), MAX_REPORT_BACKOFF);
+        assert_eq!(report_backoff(100), MAX_REPORT_BACKOFF);
+    }
+}
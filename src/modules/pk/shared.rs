@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use pkrs::model::Member;
+use poise::serenity_prelude::{self as serenity};
+use sqlx::types::Uuid;
+
+use super::api::{ApiError, PkApi, PkSystem};
+use super::db::{self, ModPkGuildRow};
+use crate::types::{Context, Data, Error};
+
+// how long to wait for a moderator to confirm planned changes
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+
+// NOTE: PluralKit doesn't tell us why a request failed, but a private group member
+//       list without a (valid) token is the most common cause, in that case we'd
+//       rather show nothing than every member of the system
+pub(crate) async fn get_group_member_ids(
+    pk: &PkApi,
+    system_id: &str,
+    group_id: &str,
+    token: &str,
+) -> Result<HashSet<Uuid>, Error> {
+    Ok(pk
+        .get_group_members(system_id, group_id, token)
+        .await
+        .map_err(|err| {
+            format!(
+                "couldn't fetch members of group `{}`, it might not exist or its member list is private and needs a token: {}",
+                group_id, err
+            )
+        })?
+        .into_iter()
+        .map(|m| m.uuid)
+        .collect())
+}
+
+// why a system can't be used, pkrs doesn't expose status codes so we check these
+// ourselves before saving anything
+pub(crate) enum SystemError {
+    NotFound(String),
+    InvalidToken,
+    FrontersPrivate,
+    MembersPrivate,
+    Unavailable(String),
+}
+
+impl SystemError {
+    pub(crate) fn message(&self) -> String {
+        match self {
+            Self::NotFound(system_id) => format!("system `{}` doesn't exist", system_id),
+            Self::InvalidToken => {
+                "PluralKit token is invalid, get a new one with `pk;token` in DMs with PluralKit"
+                    .into()
+            }
+            Self::FrontersPrivate => {
+                "the system's fronters are private, please provide a token to show them".into()
+            }
+            Self::MembersPrivate => {
+                "the system's member list is private, alter roles and front stats need a token, provide one with /setup-pk".into()
+            }
+            Self::Unavailable(err) => format!(
+                "PluralKit API is having issues, please try again later: {}",
+                err
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for SystemError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.write_str(&self.message())
+    }
+}
+impl std::fmt::Debug for SystemError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, fmt)
+    }
+}
+
+impl std::error::Error for SystemError {}
+
+impl From<ApiError> for SystemError {
+    fn from(err: ApiError) -> Self {
+        match err {
+            ApiError::InvalidToken => Self::InvalidToken,
+            err => Self::Unavailable(err.to_string()),
+        }
+    }
+}
+
+// check the system exists, the token is valid, and we can see its fronters,
+// returns the system on success
+// NOTE: the member list is only needed for some features, those check it with
+//       get_system_members themselves
+pub(crate) async fn validate_system(
+    pk: &PkApi,
+    system_id: &str,
+    token: &str,
+) -> Result<PkSystem, SystemError> {
+    let system = match pk.get_system(system_id, token).await {
+        Err(ApiError::NotFound) => return Err(SystemError::NotFound(system_id.into())),
+        result => result?,
+    };
+
+    match pk.get_system_fronters(system_id, token).await {
+        Err(ApiError::Forbidden) => return Err(SystemError::FrontersPrivate),
+        result => result?,
+    };
+
+    Ok(system)
+}
+
+// all members of the system, fails with a clear error when the member list is
+// private
+pub(crate) async fn get_system_members(
+    pk: &PkApi,
+    system_id: &str,
+    token: &str,
+) -> Result<Vec<Member>, SystemError> {
+    match pk.get_system_members(system_id, token).await {
+        Err(ApiError::Forbidden) => Err(SystemError::MembersPrivate),
+        result => Ok(result?),
+    }
+}
+
+// a system is verified if the token belongs to it, or the discord account is
+// linked to it, which only the system itself can do
+pub(crate) async fn verify_ownership(
+    pk: &PkApi,
+    system_id: &str,
+    token: &str,
+    user_id: u64,
+) -> Result<bool, SystemError> {
+    let path = match token.is_empty() {
+        false => "systems/@me".to_owned(),
+        true => format!("systems/{}", user_id),
+    };
+
+    // NOTE: uncached, @me and account lookups aren't tied to the system id
+    let body = match pk.request(&path, token).await {
+        Err(ApiError::NotFound | ApiError::Forbidden) => return Ok(false),
+        result => result?,
+    };
+
+    let system: PkSystem = serde_json::from_slice(body.as_deref().unwrap_or_default())
+        .map_err(|err| SystemError::Unavailable(err.to_string()))?;
+
+    Ok(system.id.eq_ignore_ascii_case(system_id))
+}
+
+// the system a command applies to, defaults to the only system in the guild, or
+// the invoker's own system when there are multiple
+pub(crate) async fn get_command_system(
+    ctx: Context<'_>,
+    system_id: Option<String>,
+) -> Result<ModPkGuildRow, Error> {
+    let guild_id = ctx.guild_id().ok_or("couldn't get guild from context")?;
+    let mut systems = db::get_guild_systems(&ctx.data().db, guild_id.get()).await?;
+
+    if systems.is_empty() {
+        return Err("PluralKit module not set-up, please run /setup-pk".into());
+    }
+
+    if let Some(system_id) = system_id {
+        let system_id = system_id.trim().replace("-", "").to_lowercase();
+        return systems
+            .into_iter()
+            .find(|gs| gs.system_id == system_id)
+            .ok_or(format!("system `{}` isn't set up in this server", system_id).into());
+    }
+
+    if systems.len() == 1 {
+        return Ok(systems.remove(0));
+    }
+
+    let user_id = i64::try_from(ctx.author().id.get())?;
+    let mut own_systems: Vec<ModPkGuildRow> = systems
+        .into_iter()
+        .filter(|gs| gs.user_id == user_id)
+        .collect();
+
+    match own_systems.len() {
+        1 => Ok(own_systems.remove(0)),
+        _ => Err("multiple systems are set up in this server, please specify one with the `system` option".into()),
+    }
+}
+
+// show `reply` with apply and cancel buttons, returns the button interaction when
+// the changes are applied, otherwise the message tells `action` was cancelled
+pub(crate) async fn confirm_changes(
+    ctx: Context<'_>,
+    reply: poise::CreateReply,
+    action: &str,
+) -> Result<Option<serenity::ComponentInteraction>, Error> {
+    let apply_id = format!("{}_apply", ctx.id());
+    let cancel_id = format!("{}_cancel", ctx.id());
+    let buttons = vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(&apply_id)
+            .style(serenity::ButtonStyle::Danger)
+            .label("Apply"),
+        serenity::CreateButton::new(&cancel_id)
+            .style(serenity::ButtonStyle::Secondary)
+            .label("Cancel"),
+    ])];
+
+    let reply = ctx.send(reply.components(buttons)).await?;
+
+    let ctx_id = ctx.id().to_string();
+    let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(CONFIRM_TIMEOUT)
+        .filter(move |mci| mci.data.custom_id.starts_with(&ctx_id))
+        .await
+    else {
+        reply
+            .edit(
+                ctx,
+                poise::CreateReply::default()
+                    .content(format!("no response received, {} cancelled", action))
+                    .components(vec![]),
+            )
+            .await?;
+        return Ok(None);
+    };
+
+    if mci.data.custom_id != apply_id {
+        mci.create_response(
+            ctx,
+            serenity::CreateInteractionResponse::UpdateMessage(
+                serenity::CreateInteractionResponseMessage::new()
+                    .content(format!("{} cancelled", action))
+                    .embeds(vec![])
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+        return Ok(None);
+    }
+
+    Ok(Some(mci))
+}
+
+// a channel is public unless @everyone is denied from viewing it
+fn is_public_channel(guild: &serenity::PartialGuild, channel: &serenity::GuildChannel) -> bool {
+    !channel.permission_overwrites.iter().any(|overwrite| {
+        overwrite.kind == serenity::PermissionOverwriteType::Role(guild.id.everyone_role())
+            && overwrite.deny.contains(serenity::Permissions::VIEW_CHANNEL)
+    })
+}
+
+// unverified systems could be anyone's, so their fronters and switches are only
+// shown in private channels
+pub(crate) fn ensure_may_display(
+    gs: &ModPkGuildRow,
+    guild: &serenity::PartialGuild,
+    channel: &serenity::GuildChannel,
+) -> Result<(), String> {
+    if !gs.verified && is_public_channel(guild, channel) {
+        return Err(format!(
+            "system `{}` isn't verified, it can only be shown in private channels, verify the system with /setup-pk first",
+            gs.system_id
+        ));
+    }
+
+    Ok(())
+}
+
+// systems receiving dispatch webhooks don't need to be polled as often
+pub(crate) fn receives_dispatch(data: &Data, gs: &ModPkGuildRow) -> bool {
+    data.pk_webhook_url.is_some() && gs.webhook_token.is_some()
+}
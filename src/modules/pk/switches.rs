@@ -0,0 +1,5 @@
+pub(crate) mod commands;
+pub(crate) mod db;
+pub(crate) mod history;
+pub(crate) mod stats;
+pub(crate) mod tasks;
@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+
+use poise::serenity_prelude::{self as serenity};
+use sqlx::types::chrono::Utc;
+
+use super::db;
+use super::history::{history_description, history_lines};
+use super::stats::{self, fetch_pk_switches, from_pk_switches, StatsPeriod, StatsSource};
+use crate::modules::pk::api::ApiError;
+use crate::modules::pk::privacy::{apply_privacy, PrivateMembers};
+use crate::modules::pk::shared::{
+    ensure_may_display, get_command_system, get_group_member_ids, get_system_members,
+};
+use crate::types::{Context, Error};
+use crate::util::format_significant_duration;
+
+// keeps the embed within discord's description limit
+const MAX_STATS_MEMBERS: usize = 25;
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "setup-switch-log",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn setup_switch_log(
+    ctx: Context<'_>,
+    #[description = "(optional) channel to log switches in, leave empty to disable the switch log"]
+    #[channel_types("Text")]
+    channel: Option<serenity::GuildChannel>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.partial_guild().await.ok_or("couldn't fetch guild")?;
+    let db = &ctx.data().db;
+
+    let gs = get_command_system(ctx, system).await?;
+
+    let Some(channel) = channel else {
+        // history is only kept while switches are logged, otherwise it'd have gaps
+        db::delete_switch_log(db, guild.id.get(), &gs.system_id).await?;
+        db::delete_switches(db, guild.id.get(), &gs.system_id).await?;
+
+        ctx.reply("switch log disabled, switch history removed")
+            .await?;
+        return Ok(());
+    };
+
+    if let Err(err) = ensure_may_display(&gs, &guild, &channel) {
+        ctx.reply(format!("error: {}", err)).await?;
+        return Ok(());
+    }
+
+    db::save_switch_log(db, guild.id.get(), &gs.system_id, channel.id.get()).await?;
+
+    ctx.reply(format!(
+        "switches will be logged in <#{}>, starting with the next one",
+        channel.id
+    ))
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "fronter-history",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn fronter_history(
+    ctx: Context<'_>,
+    #[description = "(optional) number of switches to show, defaults to 10"]
+    #[min = 1]
+    #[max = 25]
+    count: Option<u8>,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or("couldn't get guild from context")?
+        .get();
+    let db = &ctx.data().db;
+
+    let gs = get_command_system(ctx, system).await?;
+
+    if db::get_switch_log(db, guild_id, &gs.system_id)
+        .await?
+        .is_none()
+    {
+        ctx.reply("error: switch log not set-up, please run /setup-switch-log")
+            .await?;
+        return Ok(());
+    }
+
+    let switches =
+        db::get_switches(db, guild_id, &gs.system_id, count.unwrap_or(10).into()).await?;
+    if switches.is_empty() {
+        ctx.reply("no switches logged yet").await?;
+        return Ok(());
+    }
+
+    let embed = serenity::CreateEmbed::new()
+        .title(format!("Fronter History for `{}`", gs.system_id))
+        .description(history_description(&history_lines(&switches, Utc::now())));
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only = true,
+    rename = "front-stats",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn front_stats(
+    ctx: Context<'_>,
+    #[description = "period to show front time for"] period: StatsPeriod,
+    #[description = "(optional) number of days, for a custom period"]
+    #[min = 1]
+    #[max = 365]
+    days: Option<u16>,
+    #[description = "(optional) where to get switches from, defaults to PluralKit"] source: Option<
+        StatsSource,
+    >,
+    #[description = "(optional) system id, needed when multiple systems are set up"] system: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or("couldn't get guild from context")?
+        .get();
+    let data = ctx.data();
+
+    let gs = get_command_system(ctx, system).await?;
+
+    if days.is_some() && period != StatsPeriod::Custom {
+        ctx.reply("error: the number of days only applies to a custom period")
+            .await?;
+        return Ok(());
+    }
+    let duration = match period.duration(days) {
+        Ok(duration) => duration,
+        Err(err) => {
+            ctx.reply(format!("error: {}", err)).await?;
+            return Ok(());
+        }
+    };
+    let end = Utc::now();
+    let start = end - duration;
+
+    // NOTE: the switch log only stores who was shown, so there's no telling
+    //       nobody fronting apart from only hidden members fronting
+    let (switches, filtered) = match source.unwrap_or(StatsSource::PluralKit) {
+        StatsSource::PluralKit => {
+            let token = data.pk_tokens.decrypt_stored(&gs.token)?;
+
+            let switches = match fetch_pk_switches(&data.pk, &gs.system_id, &token, start).await {
+                Ok(switches) => switches,
+                Err(ApiError::Forbidden) => {
+                    ctx.reply("error: switch list is private, set a token with /setup-pk or use the switch log as source")
+                        .await?;
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let mut members = apply_privacy(
+                get_system_members(&data.pk, &gs.system_id, &token).await?,
+                &PrivateMembers::try_from_string(&gs.private_members)?,
+            );
+            if let Some(group) = &gs.member_group {
+                let group_members =
+                    get_group_member_ids(&data.pk, &gs.system_id, group, &token).await?;
+                members.retain(|m| group_members.contains(&m.uuid));
+            }
+
+            from_pk_switches(switches, &members)
+        }
+        StatsSource::SwitchLog => {
+            if db::get_switch_log(&data.db, guild_id, &gs.system_id)
+                .await?
+                .is_none()
+            {
+                ctx.reply("error: switch log not set-up, please run /setup-switch-log")
+                    .await?;
+                return Ok(());
+            }
+
+            (
+                db::get_switches_since(&data.db, guild_id, &gs.system_id, start).await?,
+                HashSet::new(),
+            )
+        }
+    };
+
+    let stats = stats::front_stats(&switches, &filtered, start, end);
+
+    let mut lines: Vec<String> = stats
+        .members
+        .iter()
+        .take(MAX_STATS_MEMBERS)
+        .map(|m| {
+            format!(
+                "**{}** • {} • {:.1}%",
+                m.name,
+                format_significant_duration(m.secs),
+                stats.percentage(m.secs)
+            )
+        })
+        .collect();
+    if stats.members.len() > MAX_STATS_MEMBERS {
+        lines.push(format!("+{} more", stats.members.len() - MAX_STATS_MEMBERS));
+    }
+    if stats.nobody_secs > 0 {
+        lines.push(format!(
+            "*nobody* • {} • {:.1}%",
+            format_significant_duration(stats.nobody_secs),
+            stats.percentage(stats.nobody_secs)
+        ));
+    }
+    if stats.filtered_secs > 0 {
+        lines.push(format!(
+            "*hidden members* • {} • {:.1}%",
+            format_significant_duration(stats.filtered_secs),
+            stats.percentage(stats.filtered_secs)
+        ));
+    }
+
+    let embed = serenity::CreateEmbed::new()
+        .title(format!("Front Stats for `{}`", gs.system_id))
+        .description(match lines.is_empty() {
+            true => "No Data".into(),
+            false => lines.join("\n"),
+        })
+        .field(
+            "Period",
+            format!("<t:{}:f> to <t:{}:f>", start.timestamp(), end.timestamp()),
+            true,
+        )
+        .field(
+            "Tracked",
+            format_significant_duration(stats.tracked_secs),
+            true,
+        )
+        .field("Switches", format!("{}", switches.len()), true);
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
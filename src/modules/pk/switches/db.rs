@@ -0,0 +1,197 @@
+use sqlx::types::{chrono, Uuid};
+
+use crate::types::Error;
+
+pub(crate) struct ModPkSwitchLogRow {
+    pub(crate) guild_id: u64,
+    pub(crate) system_id: String,
+    pub(crate) channel_id: u64,
+}
+
+pub(crate) async fn get_switch_logs(db: &sqlx::PgPool) -> Result<Vec<ModPkSwitchLogRow>, Error> {
+    let result = sqlx::query!("SELECT guild_id, system_id, channel_id FROM mod_pk_switch_logs")
+        .fetch_all(db)
+        .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|row| ModPkSwitchLogRow {
+            guild_id: row.guild_id.try_into().unwrap(),
+            system_id: row.system_id,
+            channel_id: row.channel_id.try_into().unwrap(),
+        })
+        .collect())
+}
+
+pub(crate) async fn get_switch_log(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<Option<ModPkSwitchLogRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT guild_id, system_id, channel_id FROM mod_pk_switch_logs WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(result.map(|row| ModPkSwitchLogRow {
+        guild_id: row.guild_id.try_into().unwrap(),
+        system_id: row.system_id,
+        channel_id: row.channel_id.try_into().unwrap(),
+    }))
+}
+
+pub(crate) async fn save_switch_log(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    channel_id: u64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO mod_pk_switch_logs (guild_id, system_id, channel_id) VALUES ($1, $2, $3) ON CONFLICT (guild_id, system_id) DO UPDATE SET channel_id = $3",
+        i64::try_from(guild_id)?,
+        system_id,
+        i64::try_from(channel_id)?,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_switch_log(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_switch_logs WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SwitchMember {
+    pub(crate) uuid: Uuid,
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ModPkSwitchRow {
+    pub(crate) switch_uuid: Uuid,
+    pub(crate) switched_at: chrono::DateTime<chrono::Utc>,
+    pub(crate) members: Vec<SwitchMember>,
+}
+
+// newest switches first
+pub(crate) async fn get_switches(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    limit: i64,
+) -> Result<Vec<ModPkSwitchRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT switch_uuid, switched_at, member_uuids, member_names FROM mod_pk_switches WHERE guild_id = $1 AND system_id = $2 ORDER BY switched_at DESC LIMIT $3",
+        i64::try_from(guild_id)?,
+        system_id,
+        limit,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|row| ModPkSwitchRow {
+            switch_uuid: row.switch_uuid,
+            switched_at: row.switched_at.and_utc(),
+            members: row
+                .member_uuids
+                .into_iter()
+                .zip(row.member_names)
+                .map(|(uuid, name)| SwitchMember { uuid, name })
+                .collect(),
+        })
+        .collect())
+}
+
+// switches after `since`, and the one before it to know who was fronting at the
+// time, newest first
+pub(crate) async fn get_switches_since(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    since: chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<ModPkSwitchRow>, Error> {
+    let result = sqlx::query!(
+        "SELECT switch_uuid, switched_at, member_uuids, member_names FROM mod_pk_switches WHERE guild_id = $1 AND system_id = $2 AND switched_at >= COALESCE((SELECT MAX(switched_at) FROM mod_pk_switches WHERE guild_id = $1 AND system_id = $2 AND switched_at <= $3), $3) ORDER BY switched_at DESC",
+        i64::try_from(guild_id)?,
+        system_id,
+        since.naive_utc(),
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(result
+        .into_iter()
+        .map(|row| ModPkSwitchRow {
+            switch_uuid: row.switch_uuid,
+            switched_at: row.switched_at.and_utc(),
+            members: row
+                .member_uuids
+                .into_iter()
+                .zip(row.member_names)
+                .map(|(uuid, name)| SwitchMember { uuid, name })
+                .collect(),
+        })
+        .collect())
+}
+
+pub(crate) async fn save_switch(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+    switch: &ModPkSwitchRow,
+) -> Result<(), Error> {
+    let (uuids, names): (Vec<Uuid>, Vec<String>) = switch
+        .members
+        .iter()
+        .map(|m| (m.uuid, m.name.clone()))
+        .unzip();
+
+    sqlx::query!(
+        "INSERT INTO mod_pk_switches (guild_id, system_id, switch_uuid, switched_at, member_uuids, member_names) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (guild_id, system_id, switch_uuid) DO NOTHING",
+        i64::try_from(guild_id)?,
+        system_id,
+        switch.switch_uuid,
+        switch.switched_at.naive_utc(),
+        &uuids,
+        &names,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_switches(
+    db: &sqlx::PgPool,
+    guild_id: u64,
+    system_id: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM mod_pk_switches WHERE guild_id = $1 AND system_id = $2",
+        i64::try_from(guild_id)?,
+        system_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+
+use sqlx::types::{
+    chrono::{DateTime, Utc},
+    Uuid,
+};
+
+use super::db::{ModPkSwitchRow, SwitchMember};
+use crate::util::format_significant_duration;
+
+// a switch with many members shouldn't take up the whole history
+const MAX_NAMES_LENGTH: usize = 300;
+
+// how the fronters changed compared to the previous switch
+#[derive(Debug, PartialEq)]
+pub(crate) struct SwitchChange<'a> {
+    pub(crate) switched_in: Vec<&'a SwitchMember>,
+    // with how long they were fronting for
+    pub(crate) switched_out: Vec<(&'a SwitchMember, u64)>,
+    pub(crate) still_fronting: Vec<&'a SwitchMember>,
+}
+
+fn member_ids(members: &[SwitchMember]) -> HashSet<Uuid> {
+    members.iter().map(|m| m.uuid).collect()
+}
+
+// switches only count when they change who's fronting, front order doesn't matter
+pub(crate) fn same_fronters(a: &[SwitchMember], b: &[SwitchMember]) -> bool {
+    member_ids(a) == member_ids(b)
+}
+
+fn secs_between(from: DateTime<Utc>, to: DateTime<Utc>) -> u64 {
+    (to - from).num_seconds().max(0) as u64
+}
+
+// `history` is newest first, a member started fronting at the oldest switch of
+// the uninterrupted run of switches they're in
+pub(crate) fn fronting_since(history: &[ModPkSwitchRow], member: Uuid) -> Option<DateTime<Utc>> {
+    history
+        .iter()
+        .take_while(|s| s.members.iter().any(|m| m.uuid == member))
+        .last()
+        .map(|s| s.switched_at)
+}
+
+// `history` holds the switches before `switch`, newest first
+pub(crate) fn switch_change<'a>(
+    switch: &'a ModPkSwitchRow,
+    history: &'a [ModPkSwitchRow],
+) -> SwitchChange<'a> {
+    let previous = history.first().map(|s| s.members.as_slice()).unwrap_or(&[]);
+    let previous_ids = member_ids(previous);
+    let current_ids = member_ids(&switch.members);
+
+    SwitchChange {
+        switched_in: switch
+            .members
+            .iter()
+            .filter(|m| !previous_ids.contains(&m.uuid))
+            .collect(),
+        switched_out: previous
+            .iter()
+            .filter(|m| !current_ids.contains(&m.uuid))
+            .map(|m| {
+                let since = fronting_since(history, m.uuid).unwrap_or(switch.switched_at);
+                (m, secs_between(since, switch.switched_at))
+            })
+            .collect(),
+        still_fronting: switch
+            .members
+            .iter()
+            .filter(|m| previous_ids.contains(&m.uuid))
+            .collect(),
+    }
+}
+
+pub(crate) fn member_names(members: &[SwitchMember]) -> String {
+    match members.is_empty() {
+        true => "*nobody*".into(),
+        false => members
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", "),
+    }
+}
+
+// one line per switch, `switches` is newest first, the newest one is still ongoing
+pub(crate) fn history_lines(switches: &[ModPkSwitchRow], now: DateTime<Utc>) -> Vec<String> {
+    switches
+        .iter()
+        .enumerate()
+        .map(|(idx, switch)| {
+            let duration = match idx {
+                0 => format!(
+                    "{} so far",
+                    format_significant_duration(secs_between(switch.switched_at, now))
+                ),
+                _ => format_significant_duration(secs_between(
+                    switch.switched_at,
+                    switches[idx - 1].switched_at,
+                )),
+            };
+
+            let mut names = member_names(&switch.members);
+            if names.chars().count() > MAX_NAMES_LENGTH {
+                names = names.chars().take(MAX_NAMES_LENGTH - 1).collect();
+                names.push('…');
+            }
+
+            format!(
+                "<t:{}:f> {} ({})",
+                switch.switched_at.timestamp(),
+                names,
+                duration
+            )
+        })
+        .collect()
+}
+
+// NOTE: embed descriptions are limited to 4096 characters, so only list as many
+//       lines as fit and summarise the rest
+pub(crate) fn history_description(lines: &[String]) -> String {
+    let mut description = String::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let line = format!("{}\n", line);
+        if description.len() + line.len() > 4000 {
+            description.push_str(&format!("... and {} more", lines.len() - idx));
+            break;
+        }
+        description.push_str(&line);
+    }
+
+    description.trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(id: u128, name: &str) -> SwitchMember {
+        SwitchMember {
+            uuid: Uuid::from_u128(id),
+            name: name.into(),
+        }
+    }
+
+    fn switch(id: u128, at: i64, members: Vec<SwitchMember>) -> ModPkSwitchRow {
+        ModPkSwitchRow {
+            switch_uuid: Uuid::from_u128(id),
+            switched_at: DateTime::from_timestamp(at, 0).unwrap(),
+            members,
+        }
+    }
+
+    #[test]
+    fn same_fronters_test() {
+        let (a, b) = (member(1, "A"), member(2, "B"));
+        assert!(same_fronters(
+            &[a.clone(), b.clone()],
+            &[b.clone(), a.clone()]
+        ));
+        assert!(!same_fronters(&[a.clone(), b.clone()], &[a]));
+        assert!(same_fronters(&[], &[]));
+    }
+
+    #[test]
+    fn switch_change_test() {
+        let (a, b, c) = (member(1, "A"), member(2, "B"), member(3, "C"));
+        // newest first: A since 0, B joined at 100
+        let history = vec![
+            switch(2, 100, vec![a.clone(), b.clone()]),
+            switch(1, 0, vec![a.clone()]),
+        ];
+        let new = switch(3, 400, vec![b.clone(), c.clone()]);
+
+        assert_eq!(
+            switch_change(&new, &history),
+            SwitchChange {
+                switched_in: vec![&c],
+                switched_out: vec![(&a, 400)],
+                still_fronting: vec![&b],
+            }
+        );
+
+        // the first switch has nothing to compare to
+        assert_eq!(
+            switch_change(&new, &[]),
+            SwitchChange {
+                switched_in: vec![&b, &c],
+                switched_out: vec![],
+                still_fronting: vec![],
+            }
+        );
+
+        // switching out to nobody
+        let new = switch(3, 160, vec![]);
+        assert_eq!(
+            switch_change(&new, &history),
+            SwitchChange {
+                switched_in: vec![],
+                switched_out: vec![(&a, 160), (&b, 60)],
+                still_fronting: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn history_description_test() {
+        let lines = vec!["a".repeat(1_500), "b".repeat(1_500), "c".repeat(1_500)];
+        let description = history_description(&lines);
+        assert!(description.ends_with("... and 1 more"));
+        assert!(description.len() <= 4096);
+
+        assert_eq!(history_description(&["a".into(), "b".into()]), "a\nb");
+    }
+
+    #[test]
+    fn history_lines_test() {
+        let (a, b) = (member(1, "A"), member(2, "B"));
+        let switches = vec![
+            switch(3, 7_200, vec![]),
+            switch(2, 3_600, vec![a.clone(), b]),
+            switch(1, 0, vec![a]),
+        ];
+
+        assert_eq!(
+            history_lines(&switches, DateTime::from_timestamp(7_230, 0).unwrap()),
+            vec![
+                "<t:7200:f> *nobody* (30s so far)",
+                "<t:3600:f> A, B (1h 0m)",
+                "<t:0:f> A (1h 0m)",
+            ]
+        );
+
+        let many = (0..100).map(|id| member(id, "Alex")).collect();
+        let line = &history_lines(
+            &[switch(4, 0, many)],
+            DateTime::from_timestamp(0, 0).unwrap(),
+        )[0];
+        assert!(line.contains("…"));
+        assert!(line.chars().count() < MAX_NAMES_LENGTH + 40);
+    }
+}
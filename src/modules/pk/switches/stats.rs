@@ -0,0 +1,328 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use pkrs::model::{Member, Switch};
+use serde_either::StringOrStruct;
+use sqlx::types::{
+    chrono::{DateTime, Utc},
+    Uuid,
+};
+
+use super::db::{ModPkSwitchRow, SwitchMember};
+use crate::modules::pk::api::{ApiError, PkApi};
+use crate::util::get_member_name;
+
+// PluralKit doesn't return more switches per request
+const SWITCHES_PER_PAGE: u32 = 100;
+// NOTE: stats for very busy systems only cover the latest switches, but a
+//       command shouldn't take minutes because of the rate limit
+const MAX_PAGES: usize = 20;
+
+const SECS_IN_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum StatsPeriod {
+    #[name = "Last Day"]
+    Day,
+    #[name = "Last Week"]
+    Week,
+    #[name = "Last 30 Days"]
+    Month,
+    #[name = "Custom Number of Days"]
+    Custom,
+}
+
+impl StatsPeriod {
+    pub(crate) fn duration(&self, days: Option<u16>) -> Result<Duration, String> {
+        match self {
+            Self::Day => Ok(Duration::from_secs(SECS_IN_DAY)),
+            Self::Week => Ok(Duration::from_secs(7 * SECS_IN_DAY)),
+            Self::Month => Ok(Duration::from_secs(30 * SECS_IN_DAY)),
+            Self::Custom => match days {
+                Some(days) => Ok(Duration::from_secs(u64::from(days) * SECS_IN_DAY)),
+                None => Err("a custom period needs the number of days".into()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum StatsSource {
+    #[name = "PluralKit"]
+    PluralKit,
+    #[name = "Switch Log"]
+    SwitchLog,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct MemberFrontTime {
+    pub(crate) name: String,
+    pub(crate) secs: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct FrontStats {
+    // longest fronting first
+    pub(crate) members: Vec<MemberFrontTime>,
+    // time we know who was fronting for, shorter than the period if the history
+    // doesn't go back far enough
+    pub(crate) tracked_secs: u64,
+    pub(crate) nobody_secs: u64,
+    // time only members that aren't shown were fronting
+    pub(crate) filtered_secs: u64,
+}
+
+impl FrontStats {
+    pub(crate) fn percentage(&self, secs: u64) -> f64 {
+        match self.tracked_secs {
+            0 => 0.,
+            tracked => secs as f64 / tracked as f64 * 100.,
+        }
+    }
+}
+
+// `switches` is newest first, time between `start` and `end` is split between
+// whoever was fronting, members fronting together both get the time, `filtered`
+// holds the switches that had members left out
+pub(crate) fn front_stats(
+    switches: &[ModPkSwitchRow],
+    filtered: &HashSet<Uuid>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> FrontStats {
+    let mut members: HashMap<Uuid, MemberFrontTime> = HashMap::new();
+    let mut tracked_secs = 0;
+    let mut nobody_secs = 0;
+    let mut filtered_secs = 0;
+
+    for (idx, switch) in switches.iter().enumerate() {
+        let from = switch.switched_at.max(start);
+        let to = match idx {
+            0 => end,
+            _ => switches[idx - 1].switched_at.min(end),
+        };
+        if to <= from {
+            continue;
+        }
+
+        let secs = (to - from).num_seconds() as u64;
+        tracked_secs += secs;
+        if switch.members.is_empty() {
+            match filtered.contains(&switch.switch_uuid) {
+                true => filtered_secs += secs,
+                false => nobody_secs += secs,
+            }
+        }
+
+        for member in &switch.members {
+            // switches are newest first, so members keep their latest name
+            members
+                .entry(member.uuid)
+                .or_insert_with(|| MemberFrontTime {
+                    name: member.name.clone(),
+                    secs: 0,
+                })
+                .secs += secs;
+        }
+    }
+
+    let mut members: Vec<MemberFrontTime> = members.into_values().collect();
+    members.sort_by(|a, b| b.secs.cmp(&a.secs).then_with(|| a.name.cmp(&b.name)));
+
+    FrontStats {
+        members,
+        tracked_secs,
+        nobody_secs,
+        filtered_secs,
+    }
+}
+
+// NOTE: PluralKit switches only contain member ids, members that aren't in
+//       `members` got deleted, are private or are outside of the member group,
+//       the ids of switches that had members left out are returned as well
+pub(crate) fn from_pk_switches(
+    switches: Vec<Switch>,
+    members: &[Member],
+) -> (Vec<ModPkSwitchRow>, HashSet<Uuid>) {
+    let members: HashMap<&str, &Member> = members.iter().map(|m| (m.id.0.as_str(), m)).collect();
+    let mut filtered = HashSet::new();
+
+    let rows = switches
+        .into_iter()
+        .filter_map(|switch| {
+            let count = switch.members.len();
+            let row = ModPkSwitchRow {
+                switch_uuid: switch.id,
+                switched_at: DateTime::from_timestamp(switch.timestamp.unix_timestamp(), 0)?,
+                members: switch
+                    .members
+                    .into_iter()
+                    .filter_map(|m| match m {
+                        StringOrStruct::String(id) => members.get(id.as_str()).copied(),
+                        StringOrStruct::Struct(_) => None,
+                    })
+                    .map(|m| SwitchMember {
+                        uuid: m.uuid,
+                        name: get_member_name(m),
+                    })
+                    .collect(),
+            };
+            if row.members.len() < count {
+                filtered.insert(row.switch_uuid);
+            }
+            Some(row)
+        })
+        .collect();
+
+    (rows, filtered)
+}
+
+// switches from PluralKit going back to the one before `start`, newest first
+pub(crate) async fn fetch_pk_switches(
+    pk: &PkApi,
+    system_id: &str,
+    token: &str,
+    start: DateTime<Utc>,
+) -> Result<Vec<Switch>, ApiError> {
+    let mut switches: Vec<Switch> = Vec::new();
+
+    for _ in 0..MAX_PAGES {
+        let before = switches
+            .last()
+            .and_then(|s| DateTime::from_timestamp(s.timestamp.unix_timestamp(), 0));
+        let page = pk
+            .get_system_switches(system_id, before, SWITCHES_PER_PAGE, token)
+            .await?;
+
+        let done = page.len() < SWITCHES_PER_PAGE as usize
+            || page
+                .last()
+                .is_some_and(|s| s.timestamp.unix_timestamp() <= start.timestamp());
+        switches.extend(page);
+
+        if done {
+            break;
+        }
+    }
+
+    Ok(switches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pk::test_member;
+
+    fn member(id: u128, name: &str) -> SwitchMember {
+        SwitchMember {
+            uuid: Uuid::from_u128(id),
+            name: name.into(),
+        }
+    }
+
+    fn switch(at: i64, members: Vec<SwitchMember>) -> ModPkSwitchRow {
+        ModPkSwitchRow {
+            switch_uuid: Uuid::from_u128(at as u128),
+            switched_at: DateTime::from_timestamp(at, 0).unwrap(),
+            members,
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn stats_period_test() {
+        assert_eq!(
+            StatsPeriod::Day.duration(None).unwrap(),
+            Duration::from_secs(86_400)
+        );
+        assert_eq!(
+            StatsPeriod::Week.duration(Some(3)).unwrap(),
+            Duration::from_secs(7 * 86_400)
+        );
+        assert_eq!(
+            StatsPeriod::Custom.duration(Some(3)).unwrap(),
+            Duration::from_secs(3 * 86_400)
+        );
+        assert!(StatsPeriod::Custom.duration(None).is_err());
+    }
+
+    #[test]
+    fn front_stats_test() {
+        let (a, b) = (member(1, "A"), member(2, "B"));
+        // newest first: A from before the window, A and B at 100, nobody at 300
+        let switches = vec![
+            switch(300, vec![]),
+            switch(100, vec![a.clone(), b.clone()]),
+            switch(-50, vec![a.clone()]),
+        ];
+
+        let stats = front_stats(&switches, &HashSet::new(), at(0), at(400));
+        assert_eq!(
+            stats,
+            FrontStats {
+                members: vec![
+                    MemberFrontTime {
+                        name: "A".into(),
+                        secs: 300,
+                    },
+                    MemberFrontTime {
+                        name: "B".into(),
+                        secs: 200,
+                    },
+                ],
+                tracked_secs: 400,
+                nobody_secs: 100,
+                filtered_secs: 0,
+            }
+        );
+        assert_eq!(stats.percentage(300), 75.);
+
+        // no history before the window, only the known part counts
+        let stats = front_stats(&switches[..2], &HashSet::new(), at(0), at(400));
+        assert_eq!(stats.tracked_secs, 300);
+        assert_eq!(stats.members[0].secs, 200);
+
+        // only hidden members fronting isn't nobody fronting
+        let filtered = HashSet::from([switches[0].switch_uuid]);
+        let stats = front_stats(&switches, &filtered, at(0), at(400));
+        assert_eq!((stats.nobody_secs, stats.filtered_secs), (0, 100));
+
+        let stats = front_stats(&[], &HashSet::new(), at(0), at(400));
+        assert!(stats.members.is_empty());
+        assert_eq!(stats.percentage(0), 0.);
+    }
+
+    #[test]
+    fn from_pk_switches_test() {
+        let members = vec![test_member(serde_json::json!({
+            "uuid": Uuid::from_u128(1),
+            "name": "alex",
+            "display_name": "Alex",
+        }))];
+        let switches: Vec<Switch> = serde_json::from_value(serde_json::json!([{
+            "id": "cc6c51ad-6a5a-4e5c-8c1c-bb7e3b8e4f01",
+            "timestamp": "2024-12-28T12:00:00Z",
+            "members": ["abcde", "fghij"],
+        }]))
+        .unwrap();
+        let switch_uuid = Uuid::parse_str("cc6c51ad-6a5a-4e5c-8c1c-bb7e3b8e4f01").unwrap();
+
+        assert_eq!(
+            from_pk_switches(switches, &members),
+            (
+                vec![ModPkSwitchRow {
+                    switch_uuid,
+                    switched_at: at(1735387200),
+                    members: vec![SwitchMember {
+                        uuid: Uuid::from_u128(1),
+                        name: "Alex".into(),
+                    }],
+                }],
+                HashSet::from([switch_uuid])
+            )
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude::{self as serenity};
+use sqlx::types::chrono::Utc;
+use tracing::{error, info, warn};
+
+use super::db::{self, ModPkSwitchLogRow, ModPkSwitchRow, SwitchMember};
+use super::history::{member_names, same_fronters, switch_change};
+use crate::modules::pk;
+use crate::modules::pk::db::ModPkGuildRow;
+use crate::modules::pk::fronters::commands::get_fronter_members;
+use crate::modules::pk::privacy::PrivateMembers;
+use crate::modules::pk::shared::{ensure_may_display, receives_dispatch};
+use crate::types::{Data, Error};
+use crate::util::{format_significant_duration, get_member_name};
+
+// NOTE: members fronting for longer than this many switches get a shorter
+//       duration in the log, that's fine
+const HISTORY_LIMIT: i64 = 100;
+
+pub(crate) async fn log_switches(ctx: &serenity::Context, data: Arc<Data>) -> Result<(), Error> {
+    log_switches_where(ctx, &data, |gs| !receives_dispatch(&data, gs)).await
+}
+
+// fallback for systems that receive dispatch webhooks, in case one got lost
+pub(crate) async fn log_dispatch_switches(
+    ctx: &serenity::Context,
+    data: Arc<Data>,
+) -> Result<(), Error> {
+    log_switches_where(ctx, &data, |gs| receives_dispatch(&data, gs)).await
+}
+
+async fn log_switches_where(
+    ctx: &serenity::Context,
+    data: &Data,
+    filter: impl Fn(&ModPkGuildRow) -> bool,
+) -> Result<(), Error> {
+    let switch_logs = db::get_switch_logs(&data.db).await?;
+    let guild_settings = pk::db::get_guild_settings(&data.db).await?;
+
+    for log in switch_logs {
+        let cur_guild_settings = guild_settings.iter().find(|gs| {
+            u64::try_from(gs.guild_id).unwrap() == log.guild_id && gs.system_id == log.system_id
+        });
+
+        let Some(gs) = cur_guild_settings else {
+            warn!(
+                guild_id = log.guild_id,
+                system_id = log.system_id,
+                "couldn't find guild settings for system"
+            );
+            continue;
+        };
+
+        if !filter(gs) {
+            continue;
+        }
+
+        let _lock = data.lock_system(log.guild_id, &log.system_id).await;
+        if let Err(err) = log_switches_for_guild(ctx, data, gs, &log).await {
+            error!(
+                guild_id = log.guild_id,
+                system_id = log.system_id,
+                channel_id = log.channel_id,
+                err
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn log_switches_for_guild(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+    log: &ModPkSwitchLogRow,
+) -> Result<(), Error> {
+    let current = get_fronter_members(
+        &data.pk,
+        &gs.system_id,
+        &data.pk_tokens.decrypt_stored(&gs.token)?,
+        gs.member_group.as_deref(),
+        &PrivateMembers::try_from_string(&gs.private_members)?,
+    )
+    .await?;
+
+    // system never registered a switch, nothing to log
+    let Some(switch_uuid) = current.switch_id else {
+        return Ok(());
+    };
+
+    let switch = ModPkSwitchRow {
+        switch_uuid,
+        switched_at: current.since.unwrap_or_else(Utc::now),
+        members: current
+            .members
+            .iter()
+            .map(|m| SwitchMember {
+                uuid: m.uuid,
+                name: get_member_name(m),
+            })
+            .collect(),
+    };
+
+    let history = db::get_switches(&data.db, log.guild_id, &gs.system_id, HISTORY_LIMIT).await?;
+
+    // already logged, or the latest switch got deleted and PluralKit went back to
+    // an older one
+    if history.iter().any(|s| s.switch_uuid == switch.switch_uuid) {
+        return Ok(());
+    }
+
+    // switches of members outside the member group don't change anything
+    if history
+        .first()
+        .is_some_and(|previous| same_fronters(&previous.members, &switch.members))
+    {
+        return Ok(());
+    }
+
+    db::save_switch(&data.db, log.guild_id, &gs.system_id, &switch).await?;
+
+    // nothing to compare the first switch to, it's only the start of the history
+    if history.is_empty() {
+        return Ok(());
+    }
+
+    post_switch(ctx, gs, log, &switch, &history).await?;
+
+    info!(
+        guild.id = log.guild_id,
+        system_id = gs.system_id,
+        "switch logged"
+    );
+
+    Ok(())
+}
+
+async fn post_switch(
+    ctx: &serenity::Context,
+    gs: &ModPkGuildRow,
+    log: &ModPkSwitchLogRow,
+    switch: &ModPkSwitchRow,
+    history: &[ModPkSwitchRow],
+) -> Result<(), Error> {
+    let guild = ctx.http.get_guild(log.guild_id.into()).await?;
+    let channel = ctx
+        .http
+        .get_channel(log.channel_id.into())
+        .await
+        .map_err(|err| {
+            format!(
+                "couldn't find switch log channel {}: {}",
+                log.channel_id, err
+            )
+        })?
+        .guild()
+        .ok_or(format!("channel {} isn't a guild channel", log.channel_id))?;
+
+    ensure_may_display(gs, &guild, &channel)?;
+
+    let change = switch_change(switch, history);
+    let mut embed = serenity::CreateEmbed::new()
+        .title("Switch")
+        .description(match switch.members.is_empty() {
+            true => "Nobody is fronting".into(),
+            false => format!("Fronting: {}", member_names(&switch.members)),
+        })
+        .timestamp(serenity::Timestamp::from_unix_timestamp(
+            switch.switched_at.timestamp(),
+        )?);
+
+    if !change.switched_in.is_empty() {
+        embed = embed.field(
+            "Switched In",
+            change
+                .switched_in
+                .iter()
+                .map(|m| m.name.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            true,
+        );
+    }
+
+    if !change.switched_out.is_empty() {
+        embed = embed.field(
+            "Switched Out",
+            change
+                .switched_out
+                .iter()
+                .map(|(m, secs)| format!("{} ({})", m.name, format_significant_duration(*secs)))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            true,
+        );
+    }
+
+    if !change.still_fronting.is_empty() {
+        embed = embed.field(
+            "Still Fronting",
+            change
+                .still_fronting
+                .iter()
+                .map(|m| m.name.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            true,
+        );
+    }
+
+    channel
+        .send_message(ctx, serenity::CreateMessage::new().embed(embed))
+        .await?;
+    Ok(())
+}
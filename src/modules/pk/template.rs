@@ -0,0 +1,71 @@
+// a template split into its fixed text and placeholders
+#[derive(Debug, PartialEq)]
+pub(crate) enum Part<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+pub(crate) fn parse_template<'a>(
+    template: &'a str,
+    placeholders: &[&str],
+) -> Result<Vec<Part<'a>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Literal(&rest[..start]));
+        }
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in `{}`", template))?;
+        let placeholder = &rest[start + 1..start + end];
+        if !placeholders.contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder `{{{}}}`, available placeholders are {}",
+                placeholder,
+                placeholders
+                    .iter()
+                    .map(|p| format!("`{{{}}}`", p))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
+
+        parts.push(Part::Placeholder(placeholder));
+        rest = &rest[start + end + 1..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+
+    Ok(parts)
+}
+
+// remove parenthesised pronouns ' (she/her)' and such
+pub(crate) fn strip_pronouns(name: &str) -> &str {
+    name.split(" (").next().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_template_test() {
+        assert_eq!(
+            parse_template("{name} (Alter)", &["name"]).unwrap(),
+            vec![Part::Placeholder("name"), Part::Literal(" (Alter)")]
+        );
+        assert!(parse_template("{name", &["name"]).is_err());
+        assert!(parse_template("{nope}", &["name"]).is_err());
+    }
+
+    #[test]
+    fn strip_pronouns_test() {
+        assert_eq!(strip_pronouns("Foo (she/her)"), "Foo");
+        assert_eq!(strip_pronouns("Foo"), "Foo");
+    }
+}
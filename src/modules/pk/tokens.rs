@@ -0,0 +1,169 @@
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use tracing::info;
+
+use super::db;
+use crate::types::Error;
+
+// prefix for encrypted tokens, PluralKit tokens never contain a ':' so anything
+// without this prefix is a token stored before we started encrypting them
+const ENCRYPTED_PREFIX: &str = "v1:";
+const NONCE_LEN: usize = 12;
+
+pub(crate) struct TokenCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+// don't leak the key into logs
+impl std::fmt::Debug for TokenCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TokenCipher")
+    }
+}
+
+impl TokenCipher {
+    // key is 32 bytes encoded as base64, generate one with `openssl rand -base64 32`
+    pub(crate) fn new(key: &str) -> Result<Self, Error> {
+        let key = BASE64_STANDARD
+            .decode(key.trim())
+            .map_err(|err| format!("token key isn't valid base64: {}", err))?;
+        if key.len() != 32 {
+            return Err(format!("token key should be 32 bytes, got {}", key.len()).into());
+        }
+
+        Ok(Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        })
+    }
+
+    pub(crate) fn is_encrypted(token: &str) -> bool {
+        token.starts_with(ENCRYPTED_PREFIX)
+    }
+
+    pub(crate) fn encrypt(&self, token: &str) -> Result<String, Error> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, token.trim().as_bytes())
+            .map_err(|err| format!("error encrypting token: {}", err))?;
+
+        Ok(format!(
+            "{}{}",
+            ENCRYPTED_PREFIX,
+            BASE64_STANDARD.encode([nonce.as_slice(), &ciphertext].concat())
+        ))
+    }
+
+    pub(crate) fn decrypt(&self, token: &str) -> Result<String, Error> {
+        let Some(encoded) = token.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Err("token isn't encrypted".into());
+        };
+
+        let data = BASE64_STANDARD.decode(encoded)?;
+        if data.len() < NONCE_LEN {
+            return Err("encrypted token is too short".into());
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "couldn't decrypt token, was the token key changed?")?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    // decrypt a token as stored in the database, no token means an empty one
+    pub(crate) fn decrypt_stored(&self, token: &Option<String>) -> Result<String, Error> {
+        match token {
+            Some(token) => self.decrypt(token),
+            None => Ok("".to_owned()),
+        }
+    }
+}
+
+// encrypt tokens that were stored before encryption was introduced
+pub(crate) async fn encrypt_plaintext_tokens(
+    db: &sqlx::PgPool,
+    tokens: &TokenCipher,
+) -> Result<(), Error> {
+    let mut count = 0;
+    for (guild_id, system_id, token) in db::get_tokens(db).await? {
+        if TokenCipher::is_encrypted(&token) {
+            continue;
+        }
+
+        db::save_token(db, guild_id, &system_id, &tokens.encrypt(&token)?).await?;
+        count += 1;
+    }
+
+    if count > 0 {
+        info!("encrypted {} plaintext PluralKit token(s)", count);
+    }
+
+    Ok(())
+}
+
+// re-encrypt all tokens with a new key, plaintext tokens get encrypted as well,
+// either all tokens are rotated or none are, so a failed rotation can be retried
+pub(crate) async fn rotate_tokens(
+    db: &sqlx::PgPool,
+    old: &TokenCipher,
+    new: &TokenCipher,
+) -> Result<usize, Error> {
+    let mut tx = db.begin().await?;
+
+    let mut count = 0;
+    for (guild_id, system_id, token) in db::get_tokens(&mut *tx).await? {
+        let plaintext = match TokenCipher::is_encrypted(&token) {
+            true => old
+                .decrypt(&token)
+                .map_err(|err| format!("guild {}: {}", guild_id, err))?,
+            false => token,
+        };
+
+        db::save_token(&mut *tx, guild_id, &system_id, &new.encrypt(&plaintext)?).await?;
+        count += 1;
+    }
+
+    tx.commit().await?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+    const OTHER_KEY: &str = "HxwdHhscGRoXGBUWExQREg8QDQ4LDAkKBwgFBgMEAQI=";
+
+    #[test]
+    fn token_cipher_test() {
+        let cipher = TokenCipher::new(KEY).unwrap();
+        let token = "a".repeat(64);
+
+        let encrypted = cipher.encrypt(&token).unwrap();
+        assert!(TokenCipher::is_encrypted(&encrypted));
+        assert!(!encrypted.contains(&token));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), token);
+
+        // nonce is random so the same token encrypts differently
+        assert_ne!(cipher.encrypt(&token).unwrap(), encrypted);
+
+        assert!(TokenCipher::new(OTHER_KEY)
+            .unwrap()
+            .decrypt(&encrypted)
+            .is_err());
+        assert!(cipher.decrypt(&token).is_err());
+        assert_eq!(cipher.decrypt_stored(&None).unwrap(), "");
+    }
+
+    #[test]
+    fn token_cipher_invalid_key_test() {
+        assert!(TokenCipher::new("not base64!").is_err());
+        assert!(TokenCipher::new("AAECAwQF").is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use poise::serenity_prelude::{self as serenity};
+use serde::Deserialize;
+use sqlx::types::Uuid;
+use tracing::{debug, error, info};
+
+use super::db::{self, ModPkGuildRow};
+use super::fronters::{db as fronters_db, tasks as fronters_tasks};
+use super::roles::{db as roles_db, tasks as roles_tasks};
+use super::switches::{db as switches_db, tasks as switches_tasks};
+use crate::types::{Data, Error};
+
+// see: https://pluralkit.me/api/dispatch/
+#[derive(Deserialize, Debug)]
+struct DispatchPayload {
+    #[serde(rename = "type")]
+    kind: String,
+    signing_token: String,
+    system_id: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum DispatchUpdate {
+    // sent when the webhook url is set, only needs a response
+    Ping,
+    Fronters,
+    // member or group changes, these can change fronter names and alter roles
+    Members,
+    Ignored,
+}
+
+impl DispatchUpdate {
+    fn from_kind(kind: &str) -> Self {
+        match kind {
+            "PING" => Self::Ping,
+            "CREATE_SWITCH" | "UPDATE_SWITCH" | "DELETE_SWITCH" | "DELETE_ALL_SWITCHES" => {
+                Self::Fronters
+            }
+            "CREATE_MEMBER"
+            | "UPDATE_MEMBER"
+            | "DELETE_MEMBER"
+            | "CREATE_GROUP"
+            | "UPDATE_GROUP"
+            | "UPDATE_GROUP_MEMBERS"
+            | "DELETE_GROUP" => Self::Members,
+            _ => Self::Ignored,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct WebhookState {
+    ctx: serenity::Context,
+    data: Arc<Data>,
+}
+
+// compare without bailing at the first difference, so the signing token can't
+// be guessed by timing responses
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+pub(crate) async fn serve(
+    ctx: serenity::Context,
+    data: Arc<Data>,
+    listen: String,
+) -> Result<(), Error> {
+    let app = Router::new()
+        .route("/pk/webhook", post(receive_dispatch))
+        .with_state(WebhookState { ctx, data });
+
+    let listener = tokio::net::TcpListener::bind(&listen).await?;
+    info!("listening for PluralKit dispatch webhooks on {}", listen);
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn receive_dispatch(
+    State(state): State<WebhookState>,
+    Json(payload): Json<DispatchPayload>,
+) -> StatusCode {
+    let Ok(system_uuid) = Uuid::parse_str(&payload.system_id) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    // NOTE: PluralKit checks that requests with an invalid signing token are
+    //       rejected before it hands out the real one, so unknown systems get
+    //       a 401 as well
+    let systems: Vec<ModPkGuildRow> =
+        match db::get_dispatch_systems(&state.data.db, system_uuid).await {
+            Ok(systems) => systems
+                .into_iter()
+                .filter(|gs| {
+                    gs.webhook_token
+                        .as_deref()
+                        .is_some_and(|token| tokens_match(token, &payload.signing_token))
+                })
+                .collect(),
+            Err(err) => {
+                error!(system_uuid = %system_uuid, "error fetching dispatch systems: {}", err);
+                return StatusCode::INTERNAL_SERVER_ERROR;
+            }
+        };
+
+    if systems.is_empty() {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let update = DispatchUpdate::from_kind(&payload.kind);
+    debug!(system_uuid = %system_uuid, kind = payload.kind, ?update, "received dispatch");
+
+    if update == DispatchUpdate::Ping || update == DispatchUpdate::Ignored {
+        return StatusCode::OK;
+    }
+
+    // respond right away, PluralKit doesn't wait for us to update discord
+    tokio::spawn(async move {
+        for gs in systems {
+            let Ok(guild_id) = u64::try_from(gs.guild_id) else {
+                continue;
+            };
+            let _lock = state.data.lock_system(guild_id, &gs.system_id).await;
+            if let Err(err) = handle_dispatch(&state.ctx, &state.data, &gs, &update).await {
+                error!(
+                    guild_id = gs.guild_id,
+                    system_id = gs.system_id,
+                    "error handling dispatch: {}",
+                    err
+                );
+            }
+        }
+    });
+
+    StatusCode::OK
+}
+
+async fn handle_dispatch(
+    ctx: &serenity::Context,
+    data: &Data,
+    gs: &ModPkGuildRow,
+    update: &DispatchUpdate,
+) -> Result<(), Error> {
+    let guild_id = u64::try_from(gs.guild_id)?;
+
+    // cached responses from before the change are stale now
+    data.pk.invalidate_system(&gs.system_id);
+
+    // every step runs on its own, so one failing feature doesn't hold up the others
+    // roles need to exist before they can be assigned to fronters
+    // unverified systems can't have alter roles
+    if *update == DispatchUpdate::Members && gs.verified {
+        let result = async {
+            if let Some(sync) = roles_db::get_role_sync_for_id(&data.db, guild_id).await? {
+                roles_tasks::sync_member_roles_for_guild(ctx, data, gs, &sync).await?;
+            }
+            Ok(())
+        };
+        log_step_error(gs, "syncing member roles", result.await);
+    }
+
+    let result = async {
+        if let Some(cat) = fronters_db::get_fronter_category(&data.db, guild_id, &gs.system_id)
+            .await?
+            .filter(|cat| !cat.disabled)
+        {
+            // member changes can change how fronters are shown without changing who's fronting
+            let force = *update == DispatchUpdate::Members;
+            fronters_tasks::update_fronters_for_guild(ctx, data, gs, &cat, force).await?;
+        }
+        Ok(())
+    };
+    log_step_error(gs, "updating fronters", result.await);
+
+    if gs.assign_fronter_roles && gs.verified {
+        log_step_error(
+            gs,
+            "updating fronter roles",
+            roles_tasks::update_fronter_roles_for_guild(ctx, data, gs).await,
+        );
+    }
+
+    if *update == DispatchUpdate::Fronters {
+        let result = async {
+            if let Some(log) =
+                switches_db::get_switch_log(&data.db, guild_id, &gs.system_id).await?
+            {
+                switches_tasks::log_switches_for_guild(ctx, data, gs, &log).await?;
+            }
+            Ok(())
+        };
+        log_step_error(gs, "logging switches", result.await);
+    }
+
+    Ok(())
+}
+
+fn log_step_error(gs: &ModPkGuildRow, step: &str, result: Result<(), Error>) {
+    if let Err(err) = result {
+        error!(
+            guild_id = gs.guild_id,
+            system_id = gs.system_id,
+            "error {} for dispatch: {}",
+            step,
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_update_test() {
+        assert_eq!(DispatchUpdate::from_kind("PING"), DispatchUpdate::Ping);
+        assert_eq!(
+            DispatchUpdate::from_kind("CREATE_SWITCH"),
+            DispatchUpdate::Fronters
+        );
+        assert_eq!(
+            DispatchUpdate::from_kind("UPDATE_MEMBER"),
+            DispatchUpdate::Members
+        );
+        assert_eq!(
+            DispatchUpdate::from_kind("UPDATE_SETTINGS"),
+            DispatchUpdate::Ignored
+        );
+    }
+
+    #[test]
+    fn tokens_match_test() {
+        assert!(tokens_match("abcdef", "abcdef"));
+        assert!(!tokens_match("abcdef", "abcdeg"));
+        assert!(!tokens_match("abcdef", "abcde"));
+        assert!(!tokens_match("abcdef", ""));
+    }
+}
@@ -3,13 +3,13 @@ use std::sync::{
     atomic::{AtomicU32, AtomicU64, Ordering},
     Mutex,
 };
+use std::time::Duration;
 
 use dashmap::DashMap;
 use num_format::{Locale, ToFormattedString};
 use poise::serenity_prelude::{self as serenity, ConnectionStage};
 use sqlx::types::chrono::{self, Utc};
-use tokio::spawn;
-use tokio_schedule::{every, Job};
+use tokio::time::{interval, MissedTickBehavior};
 use tracing::error;
 
 use crate::modules::pk;
@@ -25,6 +25,8 @@ pub(crate) struct Stats {
     pub(crate) shards: DashMap<u32, ShardStats>,
     pub(crate) total_shards: AtomicU32,
     pub(crate) connected_shards: AtomicU32,
+    // by guild id
+    pub(crate) fronter_updates: DashMap<u64, FronterUpdateStats>,
 }
 
 impl Stats {
@@ -38,6 +40,7 @@ impl Stats {
             shards: DashMap::new(),
             total_shards: AtomicU32::new(0),
             connected_shards: AtomicU32::new(0),
+            fronter_updates: DashMap::new(),
         }
     }
 
@@ -77,6 +80,39 @@ impl Stats {
     pub(crate) fn get_connected_shards(&self) -> u32 {
         self.connected_shards.load(Ordering::SeqCst)
     }
+
+    pub(crate) fn record_fronter_update(&self, guild_id: u64, duration: Duration, timed_out: bool) {
+        let mut stats = self.fronter_updates.entry(guild_id).or_default();
+        stats.last_duration = duration;
+        stats.max_duration = stats.max_duration.max(duration);
+        stats.updates += 1;
+        if timed_out {
+            stats.timeouts += 1;
+        }
+    }
+
+    // guild whose last fronter update took the longest
+    pub(crate) fn get_slowest_fronter_update(&self) -> Option<(u64, Duration)> {
+        self.fronter_updates
+            .iter()
+            .map(|entry| (*entry.key(), entry.last_duration))
+            .max_by_key(|(_, duration)| *duration)
+    }
+
+    pub(crate) fn get_fronter_update_timeouts(&self) -> u64 {
+        self.fronter_updates
+            .iter()
+            .map(|entry| entry.timeouts)
+            .sum()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct FronterUpdateStats {
+    pub(crate) last_duration: Duration,
+    pub(crate) max_duration: Duration,
+    pub(crate) updates: u64,
+    pub(crate) timeouts: u64,
 }
 
 #[derive(Debug)]
@@ -215,7 +251,17 @@ pub(crate) async fn stats(ctx: Context<'_>) -> Result<(), Error> {
         .field("Memory Usage", format!("{:.02} MiB", mem_usage_mb), true)
         .field(
             "Other Stats",
-            format!("Updating fronters for {} system(s)", fronter_systems),
+            format!(
+                "Updating fronters for {} system(s)\nSlowest guild: {}, {} timeout(s)",
+                fronter_systems,
+                stats
+                    .get_slowest_fronter_update()
+                    .map_or("N/A".into(), |(_, duration)| format!(
+                        "{} ms",
+                        duration.as_millis().to_formatted_string(&Locale::en)
+                    )),
+                stats.get_fronter_update_timeouts(),
+            ),
             true,
         )
         .footer(serenity::CreateEmbedFooter::new(
@@ -277,15 +323,41 @@ pub(crate) fn start_tasks(ctx: serenity::Context, data: Arc<Data>) {
     let sys = Arc::new(Mutex::new(sysinfo::System::new_all()));
     let pid = sysinfo::Pid::from_u32(std::process::id());
 
-    spawn(every(1).seconds().perform(move || {
-        let ctx = ctx.to_owned();
-        let data = data.to_owned();
-        let sys = sys.clone();
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(1));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
 
-        async move {
-            if let Err(err) = update_stats(&ctx, data, sys, pid) {
+            if let Err(err) = update_stats(&ctx, data.clone(), sys.clone(), pid) {
                 error!("error updating stats: {}", err);
             }
         }
-    }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_fronter_update_test() {
+        let stats = Stats::new();
+        assert_eq!(stats.get_slowest_fronter_update(), None);
+
+        stats.record_fronter_update(1, Duration::from_millis(300), false);
+        stats.record_fronter_update(2, Duration::from_millis(500), false);
+        stats.record_fronter_update(2, Duration::from_millis(100), true);
+
+        assert_eq!(
+            stats.get_slowest_fronter_update(),
+            Some((1, Duration::from_millis(300)))
+        );
+        assert_eq!(stats.get_fronter_update_timeouts(), 1);
+
+        let guild = stats.fronter_updates.get(&2).unwrap();
+        assert_eq!(guild.max_duration, Duration::from_millis(500));
+        assert_eq!(guild.updates, 2);
+    }
 }
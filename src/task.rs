@@ -1,25 +1,28 @@
 #[macro_export]
 macro_rules! spawn_task {
-    ( $interval:expr, $task:expr, $ctx:ident, $data:ident ) => {
-        use tokio::spawn;
-        use tokio_schedule::{every, Job};
+    ( $interval:expr, $task:expr, $ctx:ident, $data:ident ) => {{
+        use std::time::Duration;
+        use tokio::time::{interval_at, Instant, MissedTickBehavior};
         use tracing::{debug, error};
 
-        {
-            let data = $data.to_owned();
-            let ctx = $ctx.to_owned();
-            spawn(every($interval).seconds().perform(move || {
-                let data = data.to_owned();
-                let ctx = ctx.to_owned();
+        let data = $data.to_owned();
+        let ctx = $ctx.to_owned();
+        tokio::spawn(async move {
+            let period = Duration::from_secs($interval);
+            let mut interval = interval_at(Instant::now() + period, period);
+            // NOTE: runs taking longer than the interval delay the next one, instead
+            //       of overlapping with it or being followed by a burst of catch-up runs
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-                async move {
-                    debug!("executing {}", stringify!($task));
-                    if let Err(err) = $task(&ctx, data.clone()).await {
-                        error!("error executing {}: {}", stringify!($task), err)
-                    }
-                    debug!("executed {}", stringify!($task));
+            loop {
+                interval.tick().await;
+
+                debug!("executing {}", stringify!($task));
+                if let Err(err) = $task(&ctx, data.clone()).await {
+                    error!("error executing {}: {}", stringify!($task), err)
                 }
-            }));
-        }
-    };
+                debug!("executed {}", stringify!($task));
+            }
+        });
+    }};
 }
@@ -1,21 +1,63 @@
 use std::sync::Arc;
 
-use crate::modules::stats;
+use dashmap::DashMap;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::modules::{
+    pk::{
+        api::PkApi, fronters::display::FronterState, roles::tasks::SyncReport, tokens::TokenCipher,
+    },
+    stats,
+};
 
 #[derive(Debug)]
 pub(crate) struct Data {
     pub(crate) db: sqlx::PgPool,
     pub(crate) stats: stats::Stats,
+    pub(crate) pk_tokens: TokenCipher,
+    // shared PluralKit client, so all modules share rate limits and cache
+    pub(crate) pk: PkApi,
+    // public url of the dispatch webhook endpoint, None if webhooks are disabled
+    pub(crate) pk_webhook_url: Option<String>,
+    // fronters shown last, by guild and system id
+    pub(crate) last_fronters: DashMap<(u64, String), FronterState>,
+    // last role sync problem reported, by guild and system id
+    pub(crate) role_sync_reports: DashMap<(u64, String), SyncReport>,
+    // by guild and system id, see Data::lock_system
+    system_locks: DashMap<(u64, String), Arc<Mutex<()>>>,
 }
 
 impl Data {
-    pub(crate) fn new(db: sqlx::PgPool) -> Self {
+    pub(crate) fn new(
+        db: sqlx::PgPool,
+        pk_tokens: TokenCipher,
+        pk_webhook_url: Option<String>,
+    ) -> Self {
         Self {
             db,
             stats: stats::Stats::new(),
+            pk_tokens,
+            pk: PkApi::new(),
+            pk_webhook_url,
+            last_fronters: DashMap::new(),
+            role_sync_reports: DashMap::new(),
+            system_locks: DashMap::new(),
         }
     }
+
+    // held while a system's channels, roles and switch log are updated, so polling,
+    // dispatch webhooks and commands don't act on the same state at the same time
+    // NOTE: not reentrant, only lock where an update starts
+    pub(crate) async fn lock_system(&self, guild_id: u64, system_id: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .system_locks
+            .entry((guild_id, system_id.to_owned()))
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
 }
 
 pub(crate) type Error = Box<dyn std::error::Error + Send + Sync>;
 pub(crate) type Context<'a> = poise::Context<'a, Arc<Data>, Error>;
+pub(crate) type ApplicationContext<'a> = poise::ApplicationContext<'a, Arc<Data>, Error>;